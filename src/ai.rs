@@ -0,0 +1,253 @@
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+use rand::Rng;
+use rand_chacha::ChaChaRng;
+use std::f32::consts::PI;
+
+/// How far an AI tank can see other tanks
+const AI_VISION_RANGE: f32 = 12.0;
+/// AI tanks stop approaching and start shooting once their target is closer than this
+const AI_ATTACK_RANGE: f32 = 7.0;
+/// AI tanks try to keep this distance from the tank they are attacking
+const AI_PREFERRED_DISTANCE: f32 = 4.0;
+/// Patrol goals are picked at most this far away from the tank's current position
+const AI_PATROL_RADIUS: f32 = 6.0;
+/// Max angle between the gun and the target before the AI will shoot
+const AI_AIM_TOLERANCE_DEGS: f32 = 6.0;
+
+/// What an AI tank is currently trying to do
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AiState {
+    /// No enemy in sight, drive between random points
+    Patrol,
+    /// Drive towards the last known position of `target`
+    Chase,
+    /// `target` is in sight and in range, aim and fire
+    Attack,
+    /// Capture the enemy flag, or bring our own home
+    Objective,
+}
+
+/// Patrols, or plays for the flags in capture the flag, until it sees another tank, then chases
+/// and shoots at it
+#[derive(Clone, Debug)]
+pub struct AiController {
+    pub state: AiState,
+    /// The tank we are chasing or attacking
+    pub target: Option<Entity>,
+    /// Last known position of `target`
+    pub target_pos: Option<Vec2>,
+    /// Whether `target` was in sight at the last update
+    target_visible: bool,
+    patrol_goal: Option<Vec2>,
+    pub follower: crate::PathFollower,
+    /// Gives up on the current patrol goal when finished (we are probably stuck on a wall)
+    patrol_timer: Timer,
+    /// Limits how fast the AI fires so it doesn't dump all its ammo at once
+    fire_timer: Timer,
+}
+
+impl Default for AiController {
+    fn default() -> Self {
+        Self {
+            state: AiState::Patrol,
+            target: None,
+            target_pos: None,
+            target_visible: false,
+            patrol_goal: None,
+            follower: crate::PathFollower::default(),
+            patrol_timer: Timer::from_seconds(5.0, TimerMode::Once),
+            fire_timer: Timer::from_seconds(0.6, TimerMode::Once),
+        }
+    }
+}
+
+/// Returns the signed angle (radians) that `from` has to rotate by to reach `to`, in -PI..PI
+pub fn angle_delta(from: f32, to: f32) -> f32 {
+    (to - from + PI).rem_euclid(2.0 * PI) - PI
+}
+
+fn has_line_of_sight(
+    rapier_context: &RapierContext,
+    from_entity: Entity,
+    from: Vec2,
+    to_entity: Entity,
+    to: Vec2,
+) -> bool {
+    let delta = to - from;
+    // See what a bullet would hit, water and barriers don't block the view
+    let filter = QueryFilter::default()
+        .exclude_rigid_body(from_entity)
+        .exclude_sensors()
+        .groups(CollisionGroups::new(
+            crate::BULLET_GROUP,
+            crate::WALL_GROUP | crate::TANK_GROUP,
+        ));
+
+    match rapier_context.cast_ray(
+        from,
+        delta.normalize_or_zero(),
+        delta.length(),
+        true,
+        filter,
+    ) {
+        Some((hit, _)) => hit == to_entity,
+        None => true,
+    }
+}
+
+impl AiController {
+    /// Where the flags want us to go: home with the enemy flag, after our own flag when it is
+    /// away from home, otherwise to the enemy flag unless a teammate already has it
+    fn flag_objective(&self, obs: &crate::TankObservation) -> Option<Vec2> {
+        let team = obs.team?;
+        let own = obs.flags.iter().find(|flag| flag.team == team);
+        let enemy = obs.flags.iter().find(|flag| flag.team != team);
+
+        if enemy.is_some_and(|flag| flag.carrier == Some(obs.entity)) {
+            return own.map(|flag| flag.home);
+        }
+        if let Some(own) = own.filter(|flag| !flag.at_home()) {
+            return Some(own.pos);
+        }
+        enemy
+            .filter(|flag| flag.carrier.is_none())
+            .map(|flag| flag.pos)
+    }
+
+    fn update_target(&mut self, obs: &crate::TankObservation) {
+        let pos = obs.pos();
+
+        let visible = obs
+            .enemies()
+            .filter(|other| pos.distance(other.pos) < AI_VISION_RANGE)
+            .filter(|other| {
+                has_line_of_sight(obs.rapier_context, obs.entity, pos, other.entity, other.pos)
+            })
+            .min_by(|a, b| pos.distance(a.pos).total_cmp(&pos.distance(b.pos)));
+
+        self.target_visible = visible.is_some();
+        match visible {
+            Some(target) => {
+                self.target = Some(target.entity);
+                self.target_pos = Some(target.pos);
+                self.state = if pos.distance(target.pos) < AI_ATTACK_RANGE {
+                    AiState::Attack
+                } else {
+                    AiState::Chase
+                };
+            }
+            None => {
+                // Lost sight of our target, go look where we last saw it before giving up
+                let reached_last_seen = self
+                    .target_pos
+                    .is_none_or(|target_pos| pos.distance(target_pos) < 1.0);
+                let target_alive = self
+                    .target
+                    .is_some_and(|t| obs.tanks.iter().any(|other| other.entity == t));
+
+                let searching = matches!(self.state, AiState::Chase | AiState::Attack);
+                if searching && target_alive && !reached_last_seen {
+                    self.state = AiState::Chase;
+                } else {
+                    self.state = if self.flag_objective(obs).is_some() {
+                        AiState::Objective
+                    } else {
+                        AiState::Patrol
+                    };
+                    self.target = None;
+                    self.target_pos = None;
+                }
+            }
+        }
+    }
+
+    fn body_input(
+        &mut self,
+        obs: &crate::TankObservation,
+        rng: &mut ChaChaRng,
+    ) -> crate::TankBodyInput {
+        let pos = obs.pos();
+
+        let objective = self.flag_objective(obs);
+        let carrying = obs
+            .flags
+            .iter()
+            .any(|flag| flag.carrier == Some(obs.entity));
+        // A flag carrier heads home even under fire, the gun keeps shooting back
+        if let Some(goal) = objective.filter(|_| carrying) {
+            return self.follower.go_to(obs, goal, 0.5);
+        }
+
+        match (self.state, self.target, self.target_pos, objective) {
+            (AiState::Attack, _, Some(target_pos), _) => {
+                // In sight and close, so the path is usually a straight line
+                self.follower.go_to(obs, target_pos, AI_PREFERRED_DISTANCE)
+            }
+            (AiState::Chase, Some(target), Some(target_pos), _) => {
+                if self.target_visible {
+                    self.follower.follow_flow(obs, target, target_pos, 0.5)
+                } else {
+                    // Lost sight of it, go to where it was last seen rather than where it is
+                    self.follower.go_to(obs, target_pos, 0.5)
+                }
+            }
+            (AiState::Objective, _, _, Some(goal)) => self.follower.go_to(obs, goal, 0.5),
+            _ => {
+                self.patrol_timer.tick(obs.delta);
+                let reached = self.patrol_goal.is_none_or(|goal| pos.distance(goal) < 1.0);
+
+                if reached || self.patrol_timer.finished() {
+                    let offset = Vec2::new(
+                        rng.gen_range(-AI_PATROL_RADIUS..AI_PATROL_RADIUS),
+                        rng.gen_range(-AI_PATROL_RADIUS..AI_PATROL_RADIUS),
+                    );
+                    self.patrol_goal = Some(pos + offset);
+                    self.patrol_timer.reset();
+                }
+
+                self.follower.go_to(obs, self.patrol_goal.unwrap(), 0.5)
+            }
+        }
+    }
+
+    fn gun_input(&mut self, obs: &crate::TankObservation) -> crate::TankGunInput {
+        self.fire_timer.tick(obs.delta);
+
+        let Some(target_pos) = self.target_pos else {
+            // Keep the gun where it is when there is nothing to shoot at
+            return crate::TankGunInput::new(obs.gun_angle, false);
+        };
+
+        let delta = target_pos - obs.pos();
+        let angle = f32::atan2(delta.y, delta.x);
+        let aimed = angle_delta(obs.gun_angle, angle).abs() < AI_AIM_TOLERANCE_DEGS.to_radians();
+
+        let shoot = self.state == AiState::Attack && aimed && self.fire_timer.finished();
+        if shoot {
+            self.fire_timer.reset();
+        }
+
+        crate::TankGunInput::new(angle, shoot)
+    }
+}
+
+impl crate::TankController for AiController {
+    fn update(
+        &mut self,
+        obs: &crate::TankObservation,
+        rng: &mut ChaChaRng,
+    ) -> (crate::TankBodyInput, crate::TankGunInput) {
+        self.update_target(obs);
+        (self.body_input(obs, rng), self.gun_input(obs))
+    }
+
+    fn debug(&self) -> crate::ControllerDebug {
+        crate::ControllerDebug {
+            state: format!("{:?}", self.state),
+            target: self.target_pos,
+            target_visible: self.target_visible,
+            waypoints: self.follower.waypoints().to_vec(),
+        }
+    }
+}
@@ -0,0 +1,221 @@
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    prelude::*,
+    reflect::{TypePath, TypeUuid},
+    utils::{BoxedFuture, HashMap},
+};
+use serde::Deserialize;
+
+/// Tuning values for one kind of tank, loaded from its archetype file. Fields missing from the
+/// file keep their default value, so older files still load after a stat is added
+#[derive(Clone, Component, Debug, Deserialize)]
+#[serde(default)]
+pub struct TankStats {
+    pub health: f32,
+    pub armor: crate::Armor,
+    pub max_speed: f32,
+    pub acceleration: f32,
+    /// Deceleration when no forward or backward input is given
+    pub braking: f32,
+    pub rotate_rate_degs: f32,
+    pub gun_rotate_rate_degs: f32,
+    pub max_ammo: usize,
+    /// Seconds to reload one round
+    pub reload_time: f32,
+    pub bullet_speed: f32,
+    pub bullet_damage: f32,
+    pub bullet_bounces: u32,
+    pub bullet_lifetime: f32,
+    pub bullet_max_range: f32,
+}
+
+impl Default for TankStats {
+    fn default() -> Self {
+        Self {
+            health: 100.0,
+            armor: crate::Armor::default(),
+            max_speed: 2.0,
+            acceleration: 6.0,
+            braking: 4.0,
+            rotate_rate_degs: 140.0,
+            gun_rotate_rate_degs: 220.0,
+            max_ammo: 5,
+            reload_time: 1.0,
+            bullet_speed: 18.0,
+            bullet_damage: 35.0,
+            bullet_bounces: 1,
+            bullet_lifetime: 6.0,
+            bullet_max_range: 40.0,
+        }
+    }
+}
+
+/// A `*.tank.ron` file in `assets/tanks`
+#[derive(Clone, Debug, Deserialize, TypeUuid, TypePath)]
+#[uuid = "5d0b1c1e-8a41-4f0e-9d8c-7b3f3c2a9e61"]
+pub struct TankArchetype {
+    /// Display name
+    pub name: String,
+    pub stats: TankStats,
+}
+
+#[derive(Default)]
+pub struct TankArchetypeLoader;
+
+impl AssetLoader for TankArchetypeLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let archetype: TankArchetype = ron::de::from_bytes(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(archetype));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["tank.ron"]
+    }
+}
+
+/// Every archetype in `assets/tanks`, keyed by file name without the extension
+#[derive(Resource, Default)]
+pub struct TankArchetypes(pub HashMap<String, Handle<TankArchetype>>);
+
+/// The archetype a tank was spawned with, used to look up its `TankStats`
+#[derive(Clone, Component, Debug)]
+pub struct TankArchetypeId(pub String);
+
+/// The tank's archetype hasn't finished loading yet, gameplay waits until it has
+#[derive(Clone, Component, Debug)]
+pub struct TankStatsPending;
+
+const ARCHETYPE_EXTENSION: &str = ".tank.ron";
+
+fn load_tank_archetypes(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let mut archetypes = TankArchetypes::default();
+
+    let handles = asset_server
+        .load_folder("tanks")
+        .expect("Failed to load tank archetypes");
+
+    for handle in handles {
+        let Some(path) = asset_server.get_handle_path(&handle) else {
+            continue;
+        };
+        let file_name = path.path().file_name().unwrap().to_string_lossy();
+        let Some(id) = file_name.strip_suffix(ARCHETYPE_EXTENSION) else {
+            continue;
+        };
+
+        archetypes.0.insert(id.to_owned(), handle.typed());
+    }
+
+    commands.insert_resource(archetypes);
+}
+
+/// Copies a tank's stats into the components that depend on them. A tank getting its first
+/// stats starts with a full gun, reloads only keep ammo within the new limit
+fn apply_stats(
+    first: bool,
+    stats: &TankStats,
+    health: &mut crate::Health,
+    armor: &mut crate::Armor,
+    children: &Children,
+    q_gun: &mut Query<&mut crate::TankGun>,
+) {
+    // Keep the same fraction of health when the max changes
+    let fraction = health.current / health.max;
+    health.max = stats.health;
+    health.current = stats.health * fraction;
+
+    *armor = stats.armor.clone();
+
+    for child in children.iter() {
+        if let Ok(mut gun) = q_gun.get_mut(*child) {
+            if first {
+                *gun = crate::TankGun::new(stats.max_ammo, stats.reload_time);
+            } else {
+                gun.set_stats(stats.max_ammo, stats.reload_time);
+            }
+        }
+    }
+}
+
+/// A tank's archetype, its stats and the components `apply_stats` copies them into
+type TankStatsItem<'a> = (
+    &'a TankArchetypeId,
+    &'a mut TankStats,
+    &'a mut crate::Health,
+    &'a mut crate::Armor,
+    &'a Children,
+);
+
+fn resolve_tank_stats(
+    mut commands: Commands,
+    archetypes: Res<TankArchetypes>,
+    assets: Res<Assets<TankArchetype>>,
+    mut q_tank: Query<(Entity, TankStatsItem), With<TankStatsPending>>,
+    mut q_gun: Query<&mut crate::TankGun>,
+) {
+    for (entity, (id, mut stats, mut health, mut armor, children)) in &mut q_tank {
+        match archetypes.0.get(&id.0) {
+            Some(handle) => {
+                let Some(archetype) = assets.get(handle) else {
+                    // Still loading
+                    continue;
+                };
+                *stats = archetype.stats.clone();
+            }
+            None => {
+                warn!("Unknown tank archetype {}, using default stats", id.0);
+            }
+        }
+
+        apply_stats(true, &stats, &mut health, &mut armor, children, &mut q_gun);
+        commands.entity(entity).remove::<TankStatsPending>();
+    }
+}
+
+/// Pushes edits of archetype files to every live tank using them. Runs in `Update` because asset
+/// events only live for two frames, and a frame doesn't always run a fixed update
+fn reload_tank_archetypes(
+    mut events: EventReader<AssetEvent<TankArchetype>>,
+    assets: Res<Assets<TankArchetype>>,
+    archetypes: Res<TankArchetypes>,
+    mut q_tank: Query<TankStatsItem, Without<TankStatsPending>>,
+    mut q_gun: Query<&mut crate::TankGun>,
+) {
+    for event in events.iter() {
+        let AssetEvent::Modified { handle } = event else {
+            continue;
+        };
+        let Some(archetype) = assets.get(handle) else {
+            continue;
+        };
+        let Some((id, _)) = archetypes.0.iter().find(|(_, h)| *h == handle) else {
+            continue;
+        };
+        info!("Reloaded tank archetype {id}");
+
+        for (tank_id, mut stats, mut health, mut armor, children) in &mut q_tank {
+            if tank_id.0 == *id {
+                *stats = archetype.stats.clone();
+                apply_stats(false, &stats, &mut health, &mut armor, children, &mut q_gun);
+            }
+        }
+    }
+}
+
+pub fn init_archetype_systems(app: &mut App) {
+    app.add_asset::<TankArchetype>()
+        .init_asset_loader::<TankArchetypeLoader>();
+    app.add_systems(PreStartup, load_tank_archetypes);
+    app.add_systems(
+        FixedUpdate,
+        resolve_tank_stats.before(crate::TankSystemSet::Controllers),
+    );
+    app.add_systems(Update, reload_tank_archetypes);
+}
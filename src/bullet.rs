@@ -1,39 +1,296 @@
 use bevy::prelude::*;
 use bevy_rapier2d::prelude::*;
+use std::time::Duration;
 
 #[derive(Clone, Component, Debug)]
 pub struct Bullet {
     pub shooter: Entity,
+    /// The shooter's team, kept so friendly fire works after the shooter is destroyed
+    pub team: Option<crate::Team>,
+    /// Damage dealt to a tank hit from the side, before armor
+    pub damage: f32,
+    /// How many more times this bullet can bounce off walls before it is destroyed
+    pub bounces_left: u32,
+    /// The bullet is despawned when this finishes, even if it never hit anything
+    pub lifetime: Timer,
+    /// The bullet is despawned after travelling this far, including bounces
+    pub max_range: f32,
+    pub distance_traveled: f32,
 }
 
+impl Bullet {
+    /// Creates a bullet that is despawned on its first wall hit when `bounces` is zero
+    pub fn new(
+        shooter: Entity,
+        team: Option<crate::Team>,
+        damage: f32,
+        bounces: u32,
+        lifetime: f32,
+        max_range: f32,
+    ) -> Self {
+        Self {
+            shooter,
+            team,
+            damage,
+            bounces_left: bounces,
+            lifetime: Timer::from_seconds(lifetime, TimerMode::Once),
+            max_range,
+            distance_traveled: 0.0,
+        }
+    }
+}
+
+/// The small puff shown where a bullet hits something
+pub fn bullet_impact(pos: Vec2) -> crate::ExplosionEvent {
+    crate::ExplosionEvent {
+        pos,
+        size: 0.5,
+        length: Duration::from_secs_f32(0.3),
+    }
+}
+
+/// `rules` decides whether the bullet passes through the shooter's allies
 pub fn spawn_bullet(
     commands: &mut Commands,
-    materials: &Res<crate::Materials>,
-    tank: Entity,
+    bullet: Bullet,
+    rules: &crate::TeamRules,
     size: Vec2,
     pos: Vec2,
     vel: Vec2,
 ) {
-    let mut bullet = commands.spawn(SpriteBundle {
-        sprite: Sprite {
-            custom_size: Some(size),
-            ..Default::default()
-        },
-        texture: materials.bullet.clone(),
-        transform: Transform::from_xyz(pos.x, pos.y, 0.0),
-        ..Default::default()
-    });
-
-    bullet
-        .insert(RigidBody::Dynamic)
+    commands
+        .spawn(SpatialBundle::from_transform(Transform::from_xyz(
+            pos.x, pos.y, 0.0,
+        )))
+        // Bullets are moved by their velocity but never pushed by, or push, anything.
+        // Walls are handled by `ricochet_bullets` and tank hits through sensor events
+        .insert(RigidBody::KinematicVelocityBased)
         .insert(Velocity {
             linvel: vel,
             angvel: 0.0,
         })
         .insert(Collider::cuboid(size.x / 2.0, size.y / 2.0))
-        .insert(ColliderMassProperties::Density(3.0))
+        .insert(Sensor)
         .insert(ActiveEvents::COLLISION_EVENTS)
-        // XY plane is flat base, no gravity
-        .insert(GravityScale(0.0))
-        .insert(Bullet { shooter: tank });
+        // Kinematic bodies ignore each other by default, but bullets can shoot each other down
+        .insert(ActiveCollisionTypes::default() | ActiveCollisionTypes::KINEMATIC_KINEMATIC)
+        .insert(rules.bullet_groups(bullet.team))
+        .insert(bullet);
+}
+
+/// Reflects bullets that are about to hit a wall this step, or despawns them when they are out of
+/// bounces. Bullets hitting a breakable wall damage it and are always despawned
+pub fn ricochet_bullets(
+    mut commands: Commands,
+    mut explosions: EventWriter<crate::ExplosionEvent>,
+    time: Res<FixedTime>,
+    rapier_context: Res<RapierContext>,
+    mut q_bullet: Query<(
+        Entity,
+        &mut Bullet,
+        &mut Transform,
+        &mut Velocity,
+        &Collider,
+    )>,
+    mut walls: crate::WallDamage,
+) {
+    let dt = time.period.as_secs_f32();
+    let filter = QueryFilter::only_fixed()
+        .exclude_sensors()
+        .groups(CollisionGroups::new(crate::BULLET_GROUP, crate::WALL_GROUP));
+
+    for (entity, mut bullet, mut transform, mut vel, collider) in &mut q_bullet {
+        let pos = transform.translation.truncate();
+        let speed = vel.linvel.length();
+        let dir = vel.linvel.normalize_or_zero();
+        let radius = collider
+            .as_cuboid()
+            .map_or(0.0, |cuboid| cuboid.half_extents().max_element());
+
+        let travel = speed * dt;
+        let Some((wall, hit)) =
+            rapier_context.cast_ray_and_get_normal(pos, dir, travel + radius, true, filter)
+        else {
+            continue;
+        };
+
+        // Step just past the surface so the point is inside the tile that was hit
+        let hit_breakable = walls.damage_at(wall, hit.point - hit.normal * 0.01, bullet.damage);
+
+        // Starting inside the wall, e.g. fired by a tank pressed against it. There is no normal to
+        // bounce off, and bouncing anyway would let it out on the far side
+        let embedded = hit.toi == 0.0;
+        if hit_breakable || embedded || bullet.bounces_left == 0 {
+            commands.entity(entity).despawn_recursive();
+            explosions.send(bullet_impact(hit.point));
+            continue;
+        }
+        bullet.bounces_left -= 1;
+
+        // Move up to the wall then bounce off it, the physics step moves us away from it again
+        let contact = pos + dir * (hit.toi - radius).max(0.0);
+        transform.translation = contact.extend(transform.translation.z);
+        let v = vel.linvel;
+        vel.linvel = v - 2.0 * v.dot(hit.normal) * hit.normal;
+    }
+}
+
+fn expire_bullets(
+    mut commands: Commands,
+    time: Res<FixedTime>,
+    mut q_bullet: Query<(Entity, &mut Bullet, &Velocity)>,
+) {
+    for (entity, mut bullet, vel) in &mut q_bullet {
+        bullet.distance_traveled += vel.linvel.length() * time.period.as_secs_f32();
+
+        let expired = bullet.lifetime.tick(time.period).finished();
+        if expired || bullet.distance_traveled >= bullet.max_range {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+pub fn init_bullet_systems(app: &mut App) {
+    app.add_systems(
+        FixedUpdate,
+        (expire_bullets, ricochet_bullets)
+            .chain()
+            .after(crate::TankSystemSet::ApplyInputs)
+            .before(crate::FlushBeforePhysics),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::{ecs::system::CommandQueue, time::TimeUpdateStrategy};
+
+    /// An app running only physics and the bullet systems, one fixed step per update
+    fn bullet_app() -> App {
+        let mut app = App::new();
+        app.add_plugins((
+            MinimalPlugins,
+            AssetPlugin::default(),
+            TransformPlugin,
+            HierarchyPlugin,
+        ))
+        .add_plugins(crate::FixedPhysicsPlugin::default())
+        .add_event::<crate::ExplosionEvent>()
+        .add_event::<crate::WallDestroyedEvent>()
+        .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f32(
+            crate::DEFAULT_TIMESTEP,
+        )));
+        init_bullet_systems(&mut app);
+        app.finish();
+        app.cleanup();
+        app
+    }
+
+    /// A vertical wall, 1 unit thick, with its near face at `x`
+    fn spawn_wall(app: &mut App, x: f32) {
+        let center = x + 0.5 * x.signum();
+        app.world.spawn((
+            TransformBundle::from_transform(Transform::from_xyz(center, 0.0, 0.0)),
+            RigidBody::Fixed,
+            Collider::cuboid(0.5, 50.0),
+            CollisionGroups::new(crate::WALL_GROUP, Group::ALL),
+        ));
+    }
+
+    fn fire(app: &mut App, vel: Vec2, bounces: u32) -> Entity {
+        let bullet = Bullet::new(Entity::PLACEHOLDER, None, 10.0, bounces, 60.0, 1000.0);
+        let mut queue = CommandQueue::default();
+        let mut commands = Commands::new(&mut queue, &app.world);
+        let rules = crate::TeamRules::default();
+        spawn_bullet(
+            &mut commands,
+            bullet,
+            &rules,
+            Vec2::splat(0.2),
+            Vec2::ZERO,
+            vel,
+        );
+        queue.apply(&mut app.world);
+
+        app.world
+            .query_filtered::<Entity, With<Bullet>>()
+            .single(&app.world)
+    }
+
+    fn velocity(app: &App, bullet: Entity) -> Option<Vec2> {
+        app.world.get::<Velocity>(bullet).map(|vel| vel.linvel)
+    }
+
+    /// Fires like a tank does, from the middle of a step, so the bullet is spawned late
+    fn fire_once(mut commands: Commands, mut fired: Local<bool>) {
+        if std::mem::replace(&mut *fired, true) {
+            return;
+        }
+        let bullet = Bullet::new(Entity::PLACEHOLDER, None, 10.0, 0, 60.0, 1000.0);
+        let rules = crate::TeamRules::default();
+        let vel = Vec2::new(10.0, 0.0);
+        spawn_bullet(
+            &mut commands,
+            bullet,
+            &rules,
+            Vec2::splat(0.2),
+            Vec2::ZERO,
+            vel,
+        );
+    }
+
+    #[test]
+    fn point_blank_shot_despawns_without_bouncing() {
+        let mut app = bullet_app();
+        spawn_wall(&mut app, 0.15);
+        app.add_systems(
+            FixedUpdate,
+            fire_once.in_set(crate::TankSystemSet::ApplyInputs),
+        );
+
+        for _ in 0..10 {
+            app.update();
+        }
+        let bullets = app.world.query::<&Bullet>().iter(&app.world).count();
+        assert_eq!(bullets, 0);
+    }
+
+    #[test]
+    fn bounce_mirrors_velocity_about_wall_normal() {
+        let mut app = bullet_app();
+        spawn_wall(&mut app, 3.0);
+        let vel = Vec2::new(10.0, 4.0);
+        let bullet = fire(&mut app, vel, 1);
+
+        for _ in 0..60 {
+            app.update();
+            let now = velocity(&app, bullet).expect("bullet despawned instead of bouncing");
+            if now != vel {
+                assert!((now - Vec2::new(-10.0, 4.0)).length() < 1e-3, "{now}");
+                return;
+            }
+        }
+        panic!("bullet never reached the wall");
+    }
+
+    #[test]
+    fn despawns_after_max_bounces() {
+        let mut app = bullet_app();
+        spawn_wall(&mut app, 3.0);
+        spawn_wall(&mut app, -3.0);
+        let bullet = fire(&mut app, Vec2::new(10.0, 4.0), 1);
+
+        let mut bounced = false;
+        for _ in 0..120 {
+            app.update();
+            match velocity(&app, bullet) {
+                Some(vel) => bounced |= vel.x < 0.0,
+                None => {
+                    assert!(bounced, "despawned without bouncing");
+                    return;
+                }
+            }
+        }
+        panic!("bullet outlived its bounces");
+    }
 }
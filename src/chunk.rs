@@ -0,0 +1,184 @@
+use bevy::{prelude::*, utils::HashMap};
+use bevy_rapier2d::prelude::*;
+
+use crate::MapTiles;
+
+/// Width and height of a map chunk in tiles
+pub const CHUNK_SIZE: i32 = 16;
+/// See `MapStreaming`
+pub const STREAMING_UNLOAD_MARGIN: f32 = CHUNK_SIZE as f32 / 2.0;
+
+/// The chunk entities of a map, keyed by chunk coordinate
+#[derive(Clone, Component, Debug, Default)]
+pub struct MapChunks(pub HashMap<IVec2, Entity>);
+
+/// One `CHUNK_SIZE` square of a map with its own colliders and mesh, a child of the map entity
+#[derive(Clone, Component, Debug)]
+pub struct MapChunk {
+    /// Position in chunks, multiply by `CHUNK_SIZE` for the first tile
+    pub coord: IVec2,
+    /// A copy of this chunk's part of the map, used to tell if it needs rebuilding
+    pub tiles: MapTiles,
+}
+
+/// On each collider entity of a chunk, pointing at the map the collider belongs to
+#[derive(Clone, Component, Debug)]
+pub struct MapCollider {
+    pub map: Entity,
+}
+
+/// When present, only chunks within `radius` world units of a tank or a `StreamingAnchor` are
+/// loaded. All chunks are loaded otherwise. Loaded chunks are only unloaded once they are
+/// `STREAMING_UNLOAD_MARGIN` further away, so an anchor moving back and forth across the radius
+/// doesn't load and unload the same chunk every step
+#[derive(Clone, Debug, Resource)]
+pub struct MapStreaming {
+    pub radius: f32,
+}
+
+/// Keeps the map chunks around it loaded when `MapStreaming` is on, e.g. the camera
+#[derive(Clone, Component, Debug)]
+pub struct StreamingAnchor;
+
+/// Entities that keep the chunks around them loaded, see `MapStreaming`
+type AnchorFilter = Or<(With<crate::TankBody>, With<StreamingAnchor>)>;
+
+fn chunk_count(tiles: &MapTiles) -> IVec2 {
+    let size = tiles.size();
+    (size + IVec2::splat(CHUNK_SIZE - 1)) / CHUNK_SIZE
+}
+
+/// Colliders go on children of the chunk, as walls and water need different collision groups
+fn spawn_chunk_colliders(commands: &mut Commands, map: Entity, chunk: Entity, tiles: &MapTiles) {
+    for (collider, groups) in tiles.build_colliders() {
+        let child = commands
+            .spawn((
+                TransformBundle::default(),
+                collider,
+                groups,
+                MapCollider { map },
+            ))
+            .id();
+        commands.entity(chunk).add_child(child);
+    }
+}
+
+/// Whether `a` and `b` need the same colliders, which only depend on where tanks and bullets
+/// are blocked. A breakable wall losing hp changes the tiles but not the colliders
+fn same_colliders(a: &MapTiles, b: &MapTiles) -> bool {
+    a.size() == b.size()
+        && a.elements_row_major_iter()
+            .zip(b.elements_row_major_iter())
+            .all(|(a, b)| {
+                a.blocks_tanks() == b.blocks_tanks() && a.blocks_bullets() == b.blocks_bullets()
+            })
+}
+
+fn spawn_chunk(commands: &mut Commands, map: Entity, coord: IVec2, tiles: MapTiles) -> Entity {
+    let origin = (coord * CHUNK_SIZE).as_vec2();
+    let chunk = commands
+        .spawn(SpatialBundle::from_transform(Transform::from_translation(
+            origin.extend(0.0),
+        )))
+        .id();
+    spawn_chunk_colliders(commands, map, chunk, &tiles);
+    commands
+        .entity(chunk)
+        .insert(MapChunk { coord, tiles })
+        .set_parent(map);
+    chunk
+}
+
+/// Spawns, rebuilds and despawns map chunks. A chunk is only updated when its tiles differ
+/// from the map's, and its colliders only rebuilt when they block something new. Runs after the
+/// physics step, so the colliders it despawns already have their Rapier handles
+fn sync_map_chunks(
+    mut commands: Commands,
+    streaming: Option<Res<MapStreaming>>,
+    mut q_map: Query<(Entity, Ref<MapTiles>, &mut MapChunks, &GlobalTransform)>,
+    q_chunk: Query<&MapChunk>,
+    q_anchor: Query<&GlobalTransform, AnchorFilter>,
+) {
+    let anchors: Vec<Vec2> = q_anchor
+        .iter()
+        .map(|transform| transform.translation().truncate())
+        .collect();
+
+    for (map_entity, tiles, mut chunks, map_transform) in &mut q_map {
+        if !tiles.is_changed() && streaming.is_none() {
+            continue;
+        }
+
+        let count = chunk_count(&tiles);
+        let map_pos = map_transform.translation().truncate();
+        // Whether a chunk should be loaded, with `margin` extra radius when it already is
+        let wanted = |coord: IVec2, margin: f32| {
+            if coord.x >= count.x || coord.y >= count.y {
+                return false;
+            }
+            let Some(streaming) = &streaming else {
+                return true;
+            };
+            let center = map_pos + ((coord * CHUNK_SIZE).as_vec2() + CHUNK_SIZE as f32 / 2.0);
+            // Measure to the chunk's edge, not its center, so a tank never drives into an
+            // unloaded chunk
+            let reach =
+                streaming.radius + margin + CHUNK_SIZE as f32 * std::f32::consts::FRAC_1_SQRT_2;
+            anchors
+                .iter()
+                .any(|anchor| anchor.distance_squared(center) <= reach * reach)
+        };
+
+        chunks.0.retain(|coord, chunk| {
+            let keep = wanted(*coord, STREAMING_UNLOAD_MARGIN);
+            if !keep {
+                commands.entity(*chunk).despawn_recursive();
+            }
+            keep
+        });
+
+        for y in 0..count.y {
+            for x in 0..count.x {
+                let coord = IVec2::new(x, y);
+                // Chunks still loaded thanks to the unload margin keep getting updated
+                if !chunks.0.contains_key(&coord) && !wanted(coord, 0.0) {
+                    continue;
+                }
+
+                match chunks.0.get(&coord) {
+                    Some(chunk) => {
+                        if !tiles.is_changed() {
+                            continue;
+                        }
+                        let chunk_tiles =
+                            tiles.sub_map(coord * CHUNK_SIZE, IVec2::splat(CHUNK_SIZE));
+                        let Ok(old) = q_chunk.get(*chunk) else {
+                            continue;
+                        };
+                        if old.tiles == chunk_tiles {
+                            continue;
+                        }
+                        if !same_colliders(&old.tiles, &chunk_tiles) {
+                            commands.entity(*chunk).despawn_descendants();
+                            spawn_chunk_colliders(&mut commands, map_entity, *chunk, &chunk_tiles);
+                        }
+                        commands.entity(*chunk).insert(MapChunk {
+                            coord,
+                            tiles: chunk_tiles,
+                        });
+                    }
+                    None => {
+                        let chunk_tiles =
+                            tiles.sub_map(coord * CHUNK_SIZE, IVec2::splat(CHUNK_SIZE));
+                        let chunk = spawn_chunk(&mut commands, map_entity, coord, chunk_tiles);
+                        chunks.0.insert(coord, chunk);
+                    }
+                }
+            }
+        }
+    }
+}
+
+pub fn init_chunk_systems(app: &mut App) {
+    app.add_systems(FixedUpdate, sync_map_chunks.after(PhysicsSet::Writeback));
+}
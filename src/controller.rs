@@ -0,0 +1,312 @@
+use bevy::{ecs::system::SystemParam, prelude::*, window::PrimaryWindow};
+use bevy_rapier2d::prelude::*;
+use rand_chacha::ChaChaRng;
+use std::time::Duration;
+
+/// Another tank, as seen by a controller
+#[derive(Copy, Clone, Debug)]
+pub struct TankSighting {
+    pub entity: Entity,
+    pub pos: Vec2,
+    pub velocity: Vec2,
+    pub team: Option<crate::Team>,
+}
+
+/// A capture-the-flag flag, as seen by a controller
+#[derive(Copy, Clone, Debug)]
+pub struct FlagSighting {
+    pub team: crate::Team,
+    pub pos: Vec2,
+    pub home: Vec2,
+    pub carrier: Option<Entity>,
+}
+
+impl FlagSighting {
+    pub fn at_home(&self) -> bool {
+        self.carrier.is_none() && self.pos == self.home
+    }
+}
+
+/// The map a tank is on, as seen by a controller
+#[derive(Copy, Clone)]
+pub struct MapView<'a> {
+    pub nav: &'a crate::MapNav,
+    /// World position of the map's origin
+    pub origin: Vec2,
+}
+
+/// Everything a controller is allowed to know about the world when deciding what its tank does
+pub struct TankObservation<'a> {
+    /// The tank being controlled
+    pub entity: Entity,
+    pub team: Option<crate::Team>,
+    pub transform: &'a Transform,
+    pub velocity: Vec2,
+    pub stats: &'a crate::TankStats,
+    /// The map under the tank, `None` when it is off every map or the map is still loading
+    pub map: Option<MapView<'a>>,
+    /// Global angle (radians) the gun is pointing at
+    pub gun_angle: f32,
+    pub ammo: usize,
+    /// Time since the last update, always the fixed timestep
+    pub delta: Duration,
+    /// All other tanks in the world
+    pub tanks: &'a [TankSighting],
+    /// Every flag, empty unless the game mode uses flags
+    pub flags: &'a [FlagSighting],
+    pub rapier_context: &'a RapierContext,
+    /// Only present when the app has a window
+    pub keys: Option<&'a Input<KeyCode>>,
+    pub buttons: Option<&'a Input<MouseButton>>,
+    /// World space position of the mouse cursor, if it is inside the window
+    pub cursor: Option<Vec2>,
+}
+
+impl<'a> TankObservation<'a> {
+    pub fn pos(&self) -> Vec2 {
+        self.transform.translation.truncate()
+    }
+
+    /// Other tanks that are not on our team
+    pub fn enemies(&self) -> impl Iterator<Item = &TankSighting> {
+        self.tanks
+            .iter()
+            .filter(|other| other.entity != self.entity && !crate::allies(self.team, other.team))
+    }
+}
+
+/// What a controller is doing, drawn by the debug overlay
+#[derive(Clone, Debug, Default)]
+pub struct ControllerDebug {
+    /// Short name of what the controller is doing
+    pub state: String,
+    /// Where the tank it is after was last seen
+    pub target: Option<Vec2>,
+    /// Whether `target` is in sight
+    pub target_visible: bool,
+    /// Where the tank is driving to, in world space
+    pub waypoints: Vec<Vec2>,
+}
+
+/// Decides what a tank does every fixed update.
+/// Implement this to drive tanks from scripts, replays, the network, etc.
+/// Controllers must only use `rng` for randomness to keep matches reproducible
+pub trait TankController: Send + Sync + 'static {
+    fn update(
+        &mut self,
+        obs: &TankObservation,
+        rng: &mut ChaChaRng,
+    ) -> (crate::TankBodyInput, crate::TankGunInput);
+
+    fn debug(&self) -> ControllerDebug {
+        ControllerDebug::default()
+    }
+}
+
+/// The controller driving this tank
+#[derive(Component)]
+pub struct Controller(Box<dyn TankController>);
+
+impl Controller {
+    pub fn new(controller: impl TankController) -> Self {
+        Self(Box::new(controller))
+    }
+
+    pub fn debug(&self) -> ControllerDebug {
+        self.0.debug()
+    }
+}
+
+/// Latest input produced by this tank's controller, applied by the tank systems
+#[derive(Clone, Component, Debug, Default)]
+pub struct TankInputs {
+    pub body: crate::TankBodyInput,
+    pub gun: crate::TankGunInput,
+}
+
+/// Drives a tank with the arrow keys and aims / shoots with the mouse
+#[derive(Default)]
+pub struct KeyboardMouseController {
+    /// Whether the fire button was held during the last update.
+    /// `Input::just_pressed` is per frame, which doesn't line up with fixed updates
+    fire_held: bool,
+}
+
+impl TankController for KeyboardMouseController {
+    fn update(
+        &mut self,
+        obs: &TankObservation,
+        _rng: &mut ChaChaRng,
+    ) -> (crate::TankBodyInput, crate::TankGunInput) {
+        let pressed = |key| obs.keys.is_some_and(|keys| keys.pressed(key));
+        let axis = |key| if pressed(key) { 1.0 } else { 0.0 };
+
+        let forward = axis(KeyCode::Up);
+        let backward = axis(KeyCode::Down);
+
+        let left_rotate = axis(KeyCode::Left);
+        let right_rotate = -axis(KeyCode::Right);
+
+        let rotate = left_rotate + right_rotate;
+
+        let gun_angle = obs
+            .cursor
+            .map(|cursor| {
+                let delta = cursor - obs.pos();
+                f32::atan2(delta.y, delta.x)
+            })
+            // target current rotation if mouse outside screen
+            .unwrap_or(obs.gun_angle);
+
+        let fire_held = obs
+            .buttons
+            .is_some_and(|buttons| buttons.pressed(MouseButton::Left));
+        let shoot = fire_held && !self.fire_held;
+        self.fire_held = fire_held;
+
+        (
+            crate::TankBodyInput::new(forward, backward, rotate),
+            crate::TankGunInput::new(gun_angle, shoot),
+        )
+    }
+
+    fn debug(&self) -> ControllerDebug {
+        ControllerDebug {
+            state: "Player".into(),
+            ..Default::default()
+        }
+    }
+}
+
+/// Keyboard and mouse state, the resources are only present when the app has a window
+#[derive(SystemParam)]
+struct PlayerInput<'w, 's> {
+    keys: Option<Res<'w, Input<KeyCode>>>,
+    buttons: Option<Res<'w, Input<MouseButton>>>,
+    window: Query<'w, 's, &'static Window, With<PrimaryWindow>>,
+    q_camera: Query<'w, 's, (&'static Camera, &'static GlobalTransform)>,
+}
+
+impl PlayerInput<'_, '_> {
+    /// World space position of the mouse cursor, if it is inside the window
+    fn cursor(&self) -> Option<Vec2> {
+        let (camera, camera_transform) = self.q_camera.get_single().ok()?;
+        let cursor = self.window.get_single().ok()?.cursor_position()?;
+        let ray = camera.viewport_to_world(camera_transform, cursor)?;
+        Some(ray.origin.truncate())
+    }
+}
+
+/// Everything controllers can see besides their own tank
+#[derive(SystemParam)]
+struct WorldView<'w, 's> {
+    rapier_context: Res<'w, RapierContext>,
+    q_sightings: Query<
+        'w,
+        's,
+        (
+            Entity,
+            &'static Transform,
+            &'static Velocity,
+            Option<&'static crate::Team>,
+        ),
+        With<crate::TankBody>,
+    >,
+    q_map: Query<'w, 's, (&'static GlobalTransform, &'static crate::MapNav)>,
+    q_flag: Query<'w, 's, (&'static crate::Flag, &'static Transform)>,
+}
+
+type ControlledTank<'a> = (
+    Entity,
+    &'a Transform,
+    &'a Velocity,
+    &'a Children,
+    &'a crate::TankStats,
+    &'a mut Controller,
+    &'a mut TankInputs,
+);
+
+fn run_tank_controllers(
+    time: Res<FixedTime>,
+    mut rng: ResMut<crate::GameRng>,
+    input: PlayerInput,
+    world: WorldView,
+    mut q_tank: Query<ControlledTank, Without<crate::PendingSpawn>>,
+    q_gun: Query<(&GlobalTransform, &crate::TankGun)>,
+) {
+    let cursor = input.cursor();
+
+    let tanks: Vec<_> = world
+        .q_sightings
+        .iter()
+        .map(|(entity, transform, vel, team)| TankSighting {
+            entity,
+            pos: transform.translation.truncate(),
+            velocity: vel.linvel,
+            team: team.copied(),
+        })
+        .collect();
+
+    let flags: Vec<_> = world
+        .q_flag
+        .iter()
+        .map(|(flag, transform)| FlagSighting {
+            team: flag.team,
+            pos: transform.translation.truncate(),
+            home: flag.home,
+            carrier: flag.carrier,
+        })
+        .collect();
+
+    let maps: Vec<_> = world
+        .q_map
+        .iter()
+        .map(|(transform, nav)| MapView {
+            nav,
+            origin: transform.translation().truncate(),
+        })
+        .collect();
+
+    for (entity, transform, vel, children, stats, mut controller, mut inputs) in &mut q_tank {
+        let Some((gun_transform, gun)) = children.iter().find_map(|c| q_gun.get(*c).ok()) else {
+            continue;
+        };
+
+        let pos = transform.translation.truncate();
+        let map = maps.iter().copied().find(|map| {
+            let local = pos - map.origin;
+            local.cmpge(Vec2::ZERO).all() && local.cmplt(map.nav.grid.size().as_vec2()).all()
+        });
+
+        let obs = TankObservation {
+            entity,
+            team: tanks
+                .iter()
+                .find(|sighting| sighting.entity == entity)
+                .and_then(|sighting| sighting.team),
+            transform,
+            velocity: vel.linvel,
+            stats,
+            map,
+            gun_angle: crate::get_rotz(&gun_transform.compute_transform()),
+            ammo: gun.ammo(),
+            delta: time.period,
+            tanks: &tanks,
+            flags: &flags,
+            rapier_context: &world.rapier_context,
+            keys: input.keys.as_deref(),
+            buttons: input.buttons.as_deref(),
+            cursor,
+        };
+
+        let (body, gun) = controller.0.update(&obs, &mut rng.0);
+        *inputs = TankInputs { body, gun };
+    }
+}
+
+pub fn init_controller_systems(app: &mut App) {
+    app.add_systems(
+        FixedUpdate,
+        run_tank_controllers.in_set(crate::TankSystemSet::Controllers),
+    );
+}
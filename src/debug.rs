@@ -0,0 +1,170 @@
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+
+/// Toggles the debug overlay
+const DEBUG_OVERLAY_KEY: KeyCode = KeyCode::F3;
+/// Toggles the Rapier collider render, separately since it hides most of the overlay
+const DEBUG_PHYSICS_KEY: KeyCode = KeyCode::F4;
+/// World units per pixel of the tank labels
+const LABEL_SCALE: f32 = 1.0 / 40.0;
+
+/// Whether the gizmo overlay is drawn
+#[derive(Resource, Clone, Debug, Default)]
+pub struct DebugOverlay {
+    pub enabled: bool,
+}
+
+/// Text showing what the controller of `tank` is doing
+#[derive(Component)]
+struct DebugLabel {
+    tank: Entity,
+}
+
+/// Draws paths, AI state, aim and line of sight on top of the game, toggled with F3.
+/// F4 toggles the Rapier collider render
+pub struct TanksDebugPlugin;
+
+impl Plugin for TanksDebugPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<DebugOverlay>();
+        app.add_plugins(RapierDebugRenderPlugin {
+            enabled: false,
+            ..Default::default()
+        });
+        app.add_systems(
+            Update,
+            (
+                toggle_debug_overlay,
+                (draw_astar_paths, draw_tank_debug, update_debug_labels)
+                    .run_if(|overlay: Res<DebugOverlay>| overlay.enabled),
+                despawn_debug_labels.run_if(resource_changed::<DebugOverlay>()),
+            )
+                .chain(),
+        );
+    }
+}
+
+fn toggle_debug_overlay(
+    keys: Res<Input<KeyCode>>,
+    mut overlay: ResMut<DebugOverlay>,
+    mut rapier_debug: ResMut<DebugRenderContext>,
+) {
+    if keys.just_pressed(DEBUG_OVERLAY_KEY) {
+        overlay.enabled = !overlay.enabled;
+    }
+    if keys.just_pressed(DEBUG_PHYSICS_KEY) {
+        rapier_debug.enabled = !rapier_debug.enabled;
+    }
+}
+
+/// `AStarPath`s are in tile coordinates of the entity they are on
+fn draw_astar_paths(mut gizmos: Gizmos, q_path: Query<(&GlobalTransform, &crate::AStarPath)>) {
+    for (transform, path) in &q_path {
+        let origin = transform.translation().truncate();
+        gizmos.linestrip_2d(
+            path.path
+                .iter()
+                .map(|tile| crate::MapTiles::tile_to_world(origin, *tile)),
+            Color::GREEN,
+        );
+    }
+}
+
+fn draw_tank_debug(
+    mut gizmos: Gizmos,
+    q_tank: Query<(&Transform, &Children, &crate::TankStats, &crate::Controller)>,
+    q_gun: Query<&GlobalTransform, With<crate::TankGun>>,
+) {
+    for (transform, children, stats, controller) in &q_tank {
+        let pos = transform.translation.truncate();
+        let debug = controller.debug();
+
+        if let Some(gun) = children.iter().find_map(|c| q_gun.get(*c).ok()) {
+            let angle = crate::get_rotz(&gun.compute_transform());
+            let aim = pos + Vec2::from_angle(angle) * stats.bullet_max_range;
+            gizmos.line_2d(pos, aim, Color::ORANGE);
+        }
+
+        if !debug.waypoints.is_empty() {
+            gizmos.linestrip_2d(
+                std::iter::once(pos).chain(debug.waypoints.iter().copied()),
+                Color::CYAN,
+            );
+            for waypoint in &debug.waypoints {
+                gizmos.circle_2d(*waypoint, 0.1, Color::CYAN);
+            }
+        }
+
+        if let Some(target) = debug.target {
+            // Line of sight to the target, red once it has been lost
+            let color = if debug.target_visible {
+                Color::GREEN
+            } else {
+                Color::RED
+            };
+            gizmos.line_2d(pos, target, color);
+            gizmos.circle_2d(target, 0.5, color);
+        }
+    }
+}
+
+fn update_debug_labels(
+    mut commands: Commands,
+    q_tank: Query<(Entity, &Transform, &crate::Controller)>,
+    mut q_label: Query<
+        (Entity, &DebugLabel, &mut Text, &mut Transform),
+        Without<crate::Controller>,
+    >,
+) {
+    let mut labelled = vec![];
+    for (label_entity, label, mut text, mut label_transform) in &mut q_label {
+        let Ok((_, transform, controller)) = q_tank.get(label.tank) else {
+            commands.entity(label_entity).despawn();
+            continue;
+        };
+        labelled.push(label.tank);
+
+        let state = controller.debug().state;
+        if text.sections[0].value != state {
+            text.sections[0].value = state;
+        }
+        label_transform.translation = transform.translation + Vec3::new(0.0, 0.9, 10.0);
+    }
+
+    for (tank, transform, controller) in &q_tank {
+        if labelled.contains(&tank) {
+            continue;
+        }
+        commands.spawn((
+            Text2dBundle {
+                text: Text::from_section(
+                    controller.debug().state,
+                    TextStyle {
+                        font_size: 20.0,
+                        color: Color::WHITE,
+                        ..Default::default()
+                    },
+                ),
+                transform: Transform::from_translation(
+                    transform.translation + Vec3::new(0.0, 0.9, 10.0),
+                )
+                .with_scale(Vec3::splat(LABEL_SCALE)),
+                ..Default::default()
+            },
+            DebugLabel { tank },
+        ));
+    }
+}
+
+fn despawn_debug_labels(
+    mut commands: Commands,
+    overlay: Res<DebugOverlay>,
+    q_label: Query<Entity, With<DebugLabel>>,
+) {
+    if overlay.enabled {
+        return;
+    }
+    for entity in &q_label {
+        commands.entity(entity).despawn();
+    }
+}
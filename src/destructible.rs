@@ -0,0 +1,85 @@
+use bevy::{ecs::system::SystemParam, prelude::*};
+use std::time::Duration;
+
+use crate::{MapCollider, MapTiles, Tile};
+
+/// Sent when a breakable wall is destroyed and turns into air, anything caching paths through
+/// the map should recompute them
+#[derive(Clone, Debug, Event)]
+pub struct WallDestroyedEvent {
+    pub map: Entity,
+    /// The destroyed tile, in the map's tile coordinates
+    pub tile: IVec2,
+    /// World position of the tile's center
+    pub pos: Vec2,
+}
+
+/// Lets a system damage breakable walls, see `WallDamage::damage_at`
+#[derive(SystemParam)]
+pub struct WallDamage<'w, 's> {
+    destroyed: EventWriter<'w, WallDestroyedEvent>,
+    q_collider: Query<'w, 's, &'static MapCollider>,
+    q_map: Query<'w, 's, (&'static GlobalTransform, &'static mut MapTiles)>,
+}
+
+impl WallDamage<'_, '_> {
+    /// Deals `damage` to the breakable wall at world position `pos`, in the map that `collider`
+    /// is part of. Returns `false` and does nothing when there is no breakable wall there.
+    /// The map's changed tiles make `sync_map_chunks` rebuild only the chunk the wall is in
+    pub fn damage_at(&mut self, collider: Entity, pos: Vec2, damage: f32) -> bool {
+        let Ok(map_collider) = self.q_collider.get(collider) else {
+            return false;
+        };
+        let Ok((map_transform, mut tiles)) = self.q_map.get_mut(map_collider.map) else {
+            return false;
+        };
+
+        let map_pos = map_transform.translation().truncate();
+        let tile = MapTiles::world_to_tile(map_pos, pos);
+
+        // Only take `tiles` mutably once we know there is something to change, so other hits
+        // don't flag the map as changed
+        let Some(Tile::BreakableWall { hp }) = tiles.try_get(tile).copied() else {
+            return false;
+        };
+
+        let damage = damage.round().clamp(0.0, u8::MAX as f32) as u8;
+        let hp = hp.saturating_sub(damage);
+        let Some(tile_mut) = tiles.try_get_mut(tile) else {
+            return false;
+        };
+
+        if hp == 0 {
+            *tile_mut = Tile::Air;
+            self.destroyed.send(WallDestroyedEvent {
+                map: map_collider.map,
+                tile,
+                pos: MapTiles::tile_to_world(map_pos, tile),
+            });
+        } else {
+            *tile_mut = Tile::BreakableWall { hp };
+        }
+        true
+    }
+}
+
+fn explode_destroyed_walls(
+    mut destroyed: EventReader<WallDestroyedEvent>,
+    mut explosions: EventWriter<crate::ExplosionEvent>,
+) {
+    for wall in destroyed.iter() {
+        explosions.send(crate::ExplosionEvent {
+            pos: wall.pos,
+            size: 1.2,
+            length: Duration::from_secs_f32(0.6),
+        });
+    }
+}
+
+pub fn init_destructible_systems(app: &mut App) {
+    app.add_event::<WallDestroyedEvent>();
+    app.add_systems(
+        FixedUpdate,
+        explode_destroyed_walls.after(crate::ricochet_bullets),
+    );
+}
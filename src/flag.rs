@@ -0,0 +1,134 @@
+use bevy::prelude::*;
+
+use crate::{Side, Team};
+
+/// Tanks closer than this to a flag touch it
+const FLAG_RADIUS: f32 = 0.8;
+
+/// A capture-the-flag flag. Enemies pick it up by driving over it, and score by bringing it to
+/// their own flag while that is at home. Teammates return it home when it has been dropped
+#[derive(Clone, Component, Debug)]
+pub struct Flag {
+    pub team: Team,
+    pub home: Vec2,
+    /// The tank carrying it, it is dropped where the carrier is destroyed
+    pub carrier: Option<Entity>,
+}
+
+impl Flag {
+    pub fn at_home(&self, pos: Vec2) -> bool {
+        self.carrier.is_none() && pos == self.home
+    }
+}
+
+/// Puts a flag for each of two teams on the arena's two spawn points furthest apart, at the
+/// start of every round
+fn place_flags(
+    mut commands: Commands,
+    mode: Res<crate::ActiveGameMode>,
+    q_map: Query<(&GlobalTransform, &crate::MapTiles), With<crate::ArenaMap>>,
+    q_flag: Query<(), With<Flag>>,
+) {
+    if !mode.0.uses_flags() || !q_flag.is_empty() {
+        return;
+    }
+    let Ok((map_transform, tiles)) = q_map.get_single() else {
+        return;
+    };
+
+    // Tanks spawn around the same points, see `SpawnResolver::with_team_bases`
+    let resolver = crate::SpawnResolver::new(map_transform.translation().truncate(), tiles);
+    let Some([home_a, home_b]) = resolver.team_bases() else {
+        return;
+    };
+
+    for (team, home) in [(Team(0), home_a), (Team(1), home_b)] {
+        commands.spawn((
+            SpatialBundle::from_transform(Transform::from_translation(home.extend(0.5))),
+            Flag {
+                team,
+                home,
+                carrier: None,
+            },
+        ));
+    }
+}
+
+/// Tanks, `Without<Flag>` keeps their `Transform` apart from the flags' mutable one
+type FlagTankFilter = (With<crate::TankBody>, Without<Flag>);
+
+fn update_flags(
+    mut state: ResMut<crate::MatchState>,
+    mut q_flag: Query<(&mut Flag, &mut Transform)>,
+    q_tank: Query<(Entity, &Transform, &Team), FlagTankFilter>,
+) {
+    let mut tanks: Vec<_> = q_tank.iter().collect();
+    tanks.sort_by_key(|(entity, _, _)| *entity);
+
+    for (mut flag, mut transform) in &mut q_flag {
+        if let Some(carrier) = flag.carrier {
+            match q_tank.get(carrier) {
+                Ok((_, carrier_transform, _)) => {
+                    transform.translation = carrier_transform.translation.truncate().extend(0.5);
+                }
+                // Destroyed, leave the flag where it was last carried
+                Err(_) => flag.carrier = None,
+            }
+            continue;
+        }
+
+        let pos = transform.translation.truncate();
+        let touching = tanks.iter().find(|(_, tank_transform, _)| {
+            tank_transform.translation.truncate().distance(pos) < FLAG_RADIUS
+        });
+        let Some((tank, _, team)) = touching else {
+            continue;
+        };
+
+        if **team != flag.team {
+            flag.carrier = Some(*tank);
+        } else if !flag.at_home(pos) {
+            transform.translation = flag.home.extend(0.5);
+        }
+    }
+
+    // A carrier scores by touching its own flag while it is at home
+    let homes: Vec<(Team, Vec2)> = q_flag
+        .iter()
+        .filter(|(flag, transform)| flag.at_home(transform.translation.truncate()))
+        .map(|(flag, _)| (flag.team, flag.home))
+        .collect();
+    for (mut flag, mut transform) in &mut q_flag {
+        let Some((_, carrier_transform, team)) = flag.carrier.and_then(|c| q_tank.get(c).ok())
+        else {
+            continue;
+        };
+        let pos = carrier_transform.translation.truncate();
+        let scored = homes
+            .iter()
+            .any(|(home_team, home)| home_team == team && home.distance(pos) < FLAG_RADIUS);
+        if scored {
+            state.add_points(Side::Team(*team), 1);
+            info!(
+                "{} team captured the {} flag",
+                team.name(),
+                flag.team.name()
+            );
+            flag.carrier = None;
+            transform.translation = flag.home.extend(0.5);
+        }
+    }
+}
+
+pub fn init_flag_systems(app: &mut App) {
+    app.add_systems(
+        FixedUpdate,
+        (
+            place_flags
+                .after(crate::FixedTransformPropagation)
+                .before(crate::TankSystemSet::Controllers)
+                .run_if(not(any_with_component::<crate::MapLoading>())),
+            update_flags.in_set(crate::TankSystemSet::ApplyInputs),
+        ),
+    );
+}
@@ -0,0 +1,282 @@
+use array2d::Array2D;
+use bevy::{prelude::*, utils::HashMap};
+use std::{cmp::Reverse, collections::BinaryHeap};
+
+use crate::{MapTiles, NavGrid, PathOptions, NEIGHBOURS};
+
+/// How many tiles the target of a `FlowField` can move before `FlowField::retarget` recomputes it
+const RETARGET_DISTANCE: i32 = 2;
+
+/// Cost of the cheapest path from every tile to one target tile, so any number of tanks can
+/// head for the same target by sampling it instead of each searching for a path
+#[derive(Clone, Debug)]
+pub struct FlowField {
+    target: IVec2,
+    /// `usize::MAX` where the target can't be reached from
+    distances: Array2D<usize>,
+}
+
+impl FlowField {
+    pub fn new(grid: &NavGrid, target: IVec2) -> Self {
+        let size = grid.size();
+        let mut field = Self {
+            target,
+            distances: Array2D::filled_with(usize::MAX, size.y as usize, size.x as usize),
+        };
+        field.recompute(grid);
+        field
+    }
+
+    /// Cost of the cheapest path from `tile` to the target, `None` if there isn't one
+    pub fn distance(&self, tile: IVec2) -> Option<usize> {
+        if tile.x < 0 || tile.y < 0 {
+            return None;
+        }
+        self.distances
+            .get(tile.y as usize, tile.x as usize)
+            .copied()
+            .filter(|d| *d != usize::MAX)
+    }
+
+    fn set_distance(&mut self, tile: IVec2, distance: usize) {
+        self.distances[(tile.y as usize, tile.x as usize)] = distance;
+    }
+
+    /// Moves the target. Unlike `tiles_opened` this isn't incremental, the whole field is
+    /// recomputed, so moves of up to `RETARGET_DISTANCE` tiles are ignored to keep every tank
+    /// driving around from recomputing its field each tile. Close to a moving target the field can
+    /// lead to where it was up to that many tiles ago
+    pub fn retarget(&mut self, grid: &NavGrid, target: IVec2) {
+        let moved = (target - self.target).abs().max_element();
+        let stale =
+            moved > RETARGET_DISTANCE || (moved > 0 && self.distance(self.target).is_none());
+        if stale || grid.size() != self.size() {
+            self.target = target;
+            self.recompute(grid);
+        }
+    }
+
+    fn size(&self) -> IVec2 {
+        IVec2::new(
+            self.distances.num_columns() as i32,
+            self.distances.num_rows() as i32,
+        )
+    }
+
+    /// Dijkstra outward from the target
+    pub fn recompute(&mut self, grid: &NavGrid) {
+        let size = grid.size();
+        self.distances = Array2D::filled_with(usize::MAX, size.y as usize, size.x as usize);
+        if grid.cost(self.target).is_none() {
+            return;
+        }
+        self.set_distance(self.target, 0);
+        self.relax(grid, vec![self.target]);
+    }
+
+    /// Updates the field after `opened` tiles became cheaper or enterable, see
+    /// `NavGrid::opened_tiles`. Distances can only go down, so only the tiles that now have a
+    /// shorter way to the target are visited
+    pub fn tiles_opened(&mut self, grid: &NavGrid, opened: &[IVec2]) {
+        let mut seeds = vec![];
+        for tile in opened {
+            // Opening a tile also unblocks diagonal moves past its corners, so its neighbours
+            // need another look too
+            for step in NEIGHBOURS.iter().copied().chain([IVec2::ZERO]) {
+                let seed = *tile + step;
+                if grid.cost(seed).is_none() {
+                    continue;
+                }
+                let best = NEIGHBOURS
+                    .iter()
+                    .filter_map(|step| {
+                        let d = self.distance(seed + *step)?;
+                        Some(d + grid.step_cost(seed, *step)?)
+                    })
+                    .min();
+                if let Some(best) = best {
+                    if best < self.distance(seed).unwrap_or(usize::MAX) {
+                        self.set_distance(seed, best);
+                    }
+                }
+                if self.distance(seed).is_some() {
+                    seeds.push(seed);
+                }
+            }
+        }
+        self.relax(grid, seeds);
+    }
+
+    /// Spreads the distances of `seeds` to every tile they give a shorter path to
+    fn relax(&mut self, grid: &NavGrid, seeds: Vec<IVec2>) {
+        let mut open: BinaryHeap<Reverse<(usize, [i32; 2])>> = seeds
+            .into_iter()
+            .filter_map(|tile| Some(Reverse((self.distance(tile)?, tile.to_array()))))
+            .collect();
+
+        while let Some(Reverse((distance, tile))) = open.pop() {
+            let tile = IVec2::from_array(tile);
+            if self.distance(tile) != Some(distance) {
+                // A shorter path was found after this was queued
+                continue;
+            }
+
+            for step in NEIGHBOURS {
+                let from = tile + step;
+                if grid.cost(from).is_none() {
+                    continue;
+                }
+                let Some(cost) = grid.step_cost(from, -step) else {
+                    continue;
+                };
+                let candidate = distance + cost;
+                if candidate < self.distance(from).unwrap_or(usize::MAX) {
+                    self.set_distance(from, candidate);
+                    open.push(Reverse((candidate, from.to_array())));
+                }
+            }
+        }
+    }
+
+    /// The neighbouring tile to move to from `tile` to get closer to the target, `None` at the
+    /// target or where it can't be reached
+    pub fn next_tile(&self, grid: &NavGrid, tile: IVec2) -> Option<IVec2> {
+        let distance = self.distance(tile)?;
+        if distance == 0 {
+            return None;
+        }
+        NEIGHBOURS
+            .iter()
+            .filter_map(|step| {
+                let next = tile + *step;
+                let d = self.distance(next)?;
+                grid.step_cost(tile, *step)?;
+                Some((d, next))
+            })
+            .min_by_key(|(d, _)| *d)
+            .map(|(_, next)| next)
+    }
+}
+
+/// Tanks heading for this entity can share one `FlowField` per map
+#[derive(Clone, Component, Debug)]
+pub struct FlowFieldGoal;
+
+/// Navigation data of a map, kept up to date with its tiles by `update_map_nav`
+#[derive(Clone, Component, Debug)]
+pub struct MapNav {
+    pub grid: NavGrid,
+    /// Goes up every time `grid` changes in a way that can change paths, anything caching a
+    /// path should replan when it does
+    pub version: u64,
+    /// A field for each `FlowFieldGoal` entity that is on this map
+    pub flows: HashMap<Entity, FlowField>,
+}
+
+fn update_map_nav(
+    mut commands: Commands,
+    mut q_map: Query<(Entity, Ref<MapTiles>, &GlobalTransform, Option<&mut MapNav>)>,
+    q_goal: Query<(Entity, &GlobalTransform), With<FlowFieldGoal>>,
+) {
+    let options = PathOptions::default();
+
+    for (map_entity, tiles, map_transform, nav) in &mut q_map {
+        let Some(mut nav) = nav else {
+            commands.entity(map_entity).insert(MapNav {
+                grid: NavGrid::new(&tiles, &options),
+                version: 0,
+                flows: HashMap::default(),
+            });
+            continue;
+        };
+
+        if tiles.is_changed() {
+            let grid = NavGrid::new(&tiles, &options);
+            match nav.grid.opened_tiles(&grid) {
+                // Nothing that matters for navigation, like a wall taking damage
+                Some(opened) if opened.is_empty() => {}
+                Some(opened) => {
+                    for flow in nav.flows.values_mut() {
+                        flow.tiles_opened(&grid, &opened);
+                    }
+                    nav.version += 1;
+                }
+                None => {
+                    for flow in nav.flows.values_mut() {
+                        flow.recompute(&grid);
+                    }
+                    nav.version += 1;
+                }
+            }
+            nav.grid = grid;
+        }
+
+        let map_pos = map_transform.translation().truncate();
+        let size = nav.grid.size();
+        let nav = &mut *nav;
+        nav.flows.retain(|goal, _| q_goal.contains(*goal));
+        for (goal, transform) in &q_goal {
+            let tile = MapTiles::world_to_tile(map_pos, transform.translation().truncate());
+            let on_map = tile.cmpge(IVec2::ZERO).all() && tile.cmplt(size).all();
+            if !on_map {
+                nav.flows.remove(&goal);
+                continue;
+            }
+            match nav.flows.get_mut(&goal) {
+                Some(flow) => flow.retarget(&nav.grid, tile),
+                None => {
+                    nav.flows.insert(goal, FlowField::new(&nav.grid, tile));
+                }
+            }
+        }
+    }
+}
+
+pub fn init_flow_systems(app: &mut App) {
+    app.add_systems(
+        FixedUpdate,
+        update_map_nav.before(crate::TankSystemSet::Controllers),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROOMS: &str = "\
+############
+#....#.....#
+#.,,.#..~~.#
+#.,,....~~.#
+#....#.....#
+############
+";
+
+    fn assert_same_field(a: &FlowField, b: &FlowField) {
+        assert_eq!(a.size(), b.size());
+        for y in 0..a.size().y {
+            for x in 0..a.size().x {
+                let tile = IVec2::new(x, y);
+                assert_eq!(a.distance(tile), b.distance(tile), "at {tile}");
+            }
+        }
+    }
+
+    #[test]
+    fn retargeted_field_matches_new_field() {
+        let tiles = MapTiles::parse(ROOMS).unwrap();
+        let grid = NavGrid::new(&tiles, &PathOptions::default());
+        let (start, end) = (IVec2::new(1, 1), IVec2::new(10, 4));
+
+        let mut field = FlowField::new(&grid, start);
+        field.retarget(&grid, end);
+        assert_same_field(&field, &FlowField::new(&grid, end));
+
+        // Small moves are ignored until the target is far enough from the field's target
+        field.retarget(&grid, end - IVec2::X);
+        assert_same_field(&field, &FlowField::new(&grid, end));
+        let far = end - IVec2::X * (RETARGET_DISTANCE + 1);
+        field.retarget(&grid, far);
+        assert_same_field(&field, &FlowField::new(&grid, far));
+    }
+}
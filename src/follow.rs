@@ -0,0 +1,304 @@
+use bevy::prelude::*;
+use std::{f32::consts::PI, time::Duration};
+
+use crate::{angle_delta, TankBodyInput, TankObservation};
+
+/// Waypoints closer than this count as reached
+const WAYPOINT_RADIUS: f32 = 0.4;
+/// Tanks only reverse to waypoints closer than this, further ones are worth turning around for
+const MAX_REVERSE_DISTANCE: f32 = 3.0;
+/// Moving less than `STUCK_DISTANCE` in this many seconds while trying to drive counts as stuck.
+/// Long enough for a tank to turn all the way around in place
+const STUCK_TIME: f32 = 2.0;
+const STUCK_DISTANCE: f32 = 0.25;
+/// How long a stuck tank backs up for before trying again
+const UNSTICK_TIME: f32 = 0.6;
+/// Replan when the goal has moved this far from where the current path leads
+const REPLAN_GOAL_DISTANCE: f32 = 1.0;
+
+/// Turns waypoints into `TankBodyInput`, replanning when the goal moves, the map changes or the
+/// tank gets stuck
+#[derive(Clone, Debug)]
+pub struct PathFollower {
+    goal: Option<Vec2>,
+    /// Waypoints still to reach, the last one is `goal`
+    waypoints: Vec<Vec2>,
+    /// `MapNav::version` the path was planned on
+    planned_version: Option<u64>,
+    /// Where the tank was when it last made progress
+    progress_pos: Option<Vec2>,
+    stuck_timer: Timer,
+    /// Backing up while this is running
+    unstick_timer: Timer,
+}
+
+impl Default for PathFollower {
+    fn default() -> Self {
+        let mut unstick_timer = Timer::from_seconds(UNSTICK_TIME, TimerMode::Once);
+        unstick_timer.tick(Duration::from_secs_f32(UNSTICK_TIME));
+
+        Self {
+            goal: None,
+            waypoints: vec![],
+            planned_version: None,
+            progress_pos: None,
+            stuck_timer: Timer::from_seconds(STUCK_TIME, TimerMode::Once),
+            unstick_timer,
+        }
+    }
+}
+
+/// Body input that drives a tank towards `waypoint`, in reverse when it is close behind the
+/// tank. The tank turns in place when the waypoint is too sharp a turn to drive round at full
+/// speed, and drives an arc to it otherwise
+pub fn steer_to(obs: &TankObservation, waypoint: Vec2) -> TankBodyInput {
+    let delta = waypoint - obs.pos();
+    let distance = delta.length();
+    let heading = f32::atan2(delta.y, delta.x);
+    let rotation = crate::get_rotz(obs.transform);
+
+    let forward_error = angle_delta(rotation, heading);
+    let backward_error = angle_delta(rotation + PI, heading);
+    let reverse = distance < MAX_REVERSE_DISTANCE && backward_error.abs() < forward_error.abs();
+    let error = if reverse {
+        backward_error
+    } else {
+        forward_error
+    };
+
+    // Radius of the tightest circle the tank drives at full speed, and of the circle through the
+    // waypoint that the tank is already heading along
+    let min_radius = obs.stats.max_speed / obs.stats.rotate_rate_degs.to_radians();
+    let needed_radius = distance / (2.0 * error.sin().abs()).max(f32::EPSILON);
+    let throttle = if error.abs() < PI / 2.0 && needed_radius >= min_radius {
+        1.0
+    } else {
+        0.0
+    };
+
+    // Full rotation speed until we are ~30 degrees away, then ease off to avoid oscillating
+    let rotate = (error / 30f32.to_radians()).clamp(-1.0, 1.0);
+
+    if reverse {
+        TankBodyInput::new(0.0, throttle, rotate)
+    } else {
+        TankBodyInput::new(throttle, 0.0, rotate)
+    }
+}
+
+impl PathFollower {
+    pub fn goal(&self) -> Option<Vec2> {
+        self.goal
+    }
+
+    /// The waypoints still to reach, the last one is the goal
+    pub fn waypoints(&self) -> &[Vec2] {
+        &self.waypoints
+    }
+
+    fn plan(&mut self, obs: &TankObservation, goal: Vec2) {
+        self.goal = Some(goal);
+        self.planned_version = obs.map.map(|map| map.nav.version);
+        // Without a path, drive straight at the goal and hope for the best
+        self.waypoints = obs
+            .map
+            .and_then(|map| map.nav.grid.find_path(map.origin, obs.pos(), goal))
+            .map_or_else(|| vec![goal], |path| path.waypoints);
+    }
+
+    /// Drives along a path to `goal`, stopping within `stop_distance` of it
+    pub fn go_to(
+        &mut self,
+        obs: &TankObservation,
+        goal: Vec2,
+        stop_distance: f32,
+    ) -> TankBodyInput {
+        let goal_moved = self
+            .goal
+            .is_none_or(|old| old.distance(goal) > REPLAN_GOAL_DISTANCE);
+        let map_changed = self.planned_version != obs.map.map(|map| map.nav.version);
+        if goal_moved || map_changed || self.waypoints.is_empty() {
+            self.plan(obs, goal);
+        }
+        self.drive(obs, stop_distance)
+    }
+
+    /// Drives towards `target` using its flow field, which is cheaper than `go_to` when many
+    /// tanks are heading for the same tank. Falls back to `go_to(target_pos)` without a field
+    pub fn follow_flow(
+        &mut self,
+        obs: &TankObservation,
+        target: Entity,
+        target_pos: Vec2,
+        stop_distance: f32,
+    ) -> TankBodyInput {
+        let next = obs.map.and_then(|map| {
+            let flow = map.nav.flows.get(&target)?;
+            let tile = crate::MapTiles::world_to_tile(map.origin, obs.pos());
+            let next = flow.next_tile(&map.nav.grid, tile)?;
+            Some(crate::MapTiles::tile_to_world(map.origin, next))
+        });
+
+        match next {
+            Some(next) => {
+                self.goal = Some(target_pos);
+                self.waypoints = vec![next, target_pos];
+                // Plan properly if we have to fall back to `go_to`
+                self.planned_version = None;
+                self.drive(obs, stop_distance)
+            }
+            None => self.go_to(obs, target_pos, stop_distance),
+        }
+    }
+
+    /// Returns `true` once when the tank hasn't made progress for `STUCK_TIME`
+    fn check_stuck(&mut self, obs: &TankObservation) -> bool {
+        let pos = obs.pos();
+        let progress_pos = *self.progress_pos.get_or_insert(pos);
+        if pos.distance(progress_pos) > STUCK_DISTANCE {
+            self.progress_pos = Some(pos);
+            self.stuck_timer.reset();
+            return false;
+        }
+
+        if self.stuck_timer.tick(obs.delta).finished() {
+            self.progress_pos = Some(pos);
+            self.stuck_timer.reset();
+            return true;
+        }
+        false
+    }
+
+    fn drive(&mut self, obs: &TankObservation, stop_distance: f32) -> TankBodyInput {
+        let pos = obs.pos();
+
+        while self.waypoints.len() > 1 && pos.distance(self.waypoints[0]) < WAYPOINT_RADIUS {
+            self.waypoints.remove(0);
+        }
+        let Some(&waypoint) = self.waypoints.first() else {
+            return TankBodyInput::default();
+        };
+
+        let arrived = self.waypoints.len() == 1
+            && pos.distance(waypoint) < stop_distance.max(WAYPOINT_RADIUS);
+        if arrived {
+            self.progress_pos = None;
+            self.stuck_timer.reset();
+            return TankBodyInput::default();
+        }
+
+        if self.check_stuck(obs) {
+            self.unstick_timer.reset();
+            if let Some(goal) = self.goal {
+                self.plan(obs, goal);
+            }
+        }
+        if !self.unstick_timer.finished() {
+            self.unstick_timer.tick(obs.delta);
+            // Drive away from whatever we ran into, which is probably between us and the waypoint
+            let delta = waypoint - pos;
+            let error = angle_delta(crate::get_rotz(obs.transform), f32::atan2(delta.y, delta.x));
+            return if error.abs() < PI / 2.0 {
+                TankBodyInput::new(0.0, 1.0, 0.0)
+            } else {
+                TankBodyInput::new(1.0, 0.0, 0.0)
+            };
+        }
+
+        steer_to(obs, waypoint)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy_rapier2d::prelude::RapierContext;
+
+    /// `steer_to` for a tank with the default stats at (1, 2) facing `rotation` radians
+    fn steer(rotation: f32, waypoint: Vec2) -> TankBodyInput {
+        let transform =
+            Transform::from_xyz(1.0, 2.0, 0.0).with_rotation(Quat::from_rotation_z(rotation));
+        let stats = crate::TankStats::default();
+        let rapier_context = RapierContext::default();
+        let obs = TankObservation {
+            entity: Entity::from_raw(0),
+            team: None,
+            transform: &transform,
+            velocity: Vec2::ZERO,
+            stats: &stats,
+            map: None,
+            gun_angle: rotation,
+            ammo: 0,
+            delta: Duration::from_secs_f32(crate::DEFAULT_TIMESTEP),
+            tanks: &[],
+            flags: &[],
+            rapier_context: &rapier_context,
+            keys: None,
+            buttons: None,
+            cursor: None,
+        };
+        steer_to(&obs, Vec2::new(1.0, 2.0) + waypoint)
+    }
+
+    #[test]
+    fn drives_straight_to_waypoint_ahead() {
+        assert_eq!(
+            steer(0.0, Vec2::new(5.0, 0.0)),
+            TankBodyInput::new(1.0, 0.0, 0.0)
+        );
+        assert_eq!(
+            steer(PI / 2.0, Vec2::new(0.0, 5.0)),
+            TankBodyInput::new(1.0, 0.0, 0.0)
+        );
+    }
+
+    #[test]
+    fn reverses_to_waypoint_close_behind() {
+        assert_eq!(
+            steer(0.0, Vec2::new(-2.0, 0.0)),
+            TankBodyInput::new(0.0, 1.0, 0.0)
+        );
+    }
+
+    #[test]
+    fn turns_around_for_waypoint_far_behind() {
+        assert_eq!(
+            steer(0.0, Vec2::new(-10.0, 0.5)),
+            TankBodyInput::new(0.0, 0.0, 1.0)
+        );
+        assert_eq!(
+            steer(0.0, Vec2::new(-10.0, -0.5)),
+            TankBodyInput::new(0.0, 0.0, -1.0)
+        );
+    }
+
+    #[test]
+    fn turns_in_place_for_waypoint_to_the_side() {
+        // Just behind the side, so driving forward would take the tank further away
+        assert_eq!(
+            steer(0.0, Vec2::new(-0.5, 5.0)),
+            TankBodyInput::new(0.0, 0.0, 1.0)
+        );
+        assert_eq!(
+            steer(0.0, Vec2::new(-0.5, -5.0)),
+            TankBodyInput::new(0.0, 0.0, -1.0)
+        );
+        // Too sharp a turn to drive round at full speed
+        assert_eq!(
+            steer(0.0, Vec2::new(0.5, 0.5)),
+            TankBodyInput::new(0.0, 0.0, 1.0)
+        );
+    }
+
+    #[test]
+    fn arcs_to_waypoint_diagonally_ahead() {
+        assert_eq!(
+            steer(0.0, Vec2::new(1.0, 1.0)),
+            TankBodyInput::new(1.0, 0.0, 1.0)
+        );
+        assert_eq!(
+            steer(0.0, Vec2::new(1.0, -1.0)),
+            TankBodyInput::new(1.0, 0.0, -1.0)
+        );
+    }
+}
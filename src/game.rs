@@ -1,149 +1,427 @@
 use std::time::Duration;
 
-use bevy::{ecs::query::ReadOnlyWorldQuery, prelude::*, render::camera::ScalingMode};
+use bevy::{
+    ecs::{query::ReadOnlyWorldQuery, system::SystemParam},
+    prelude::*,
+    transform::systems::{propagate_transforms, sync_simple_transforms},
+};
 use bevy_rapier2d::prelude::*;
+use rand::SeedableRng;
+use rand_chacha::ChaChaRng;
 
-#[derive(Resource)]
-pub struct Materials {
-    pub bullet: Handle<Image>,
-    pub tank_base: Handle<Image>,
-    pub tank_gun: Handle<Image>,
-    pub explosion: Handle<TextureAtlas>,
-    pub wall_material: Handle<ColorMaterial>,
+/// A visual explosion should be spawned, ignored when running headless
+#[derive(Clone, Debug, Event)]
+pub struct ExplosionEvent {
+    pub pos: Vec2,
+    /// Width and height of the explosion sprite
+    pub size: f32,
+    pub length: Duration,
+}
+
+/// How the match started by `start_game` is set up
+#[derive(Clone, Debug, Resource)]
+pub struct MatchSettings {
+    /// Spawn a tank driven by the local keyboard and mouse, otherwise all tanks are AI
+    pub human_player: bool,
+    /// Seeds map generation and `GameRng`. The same seed and inputs always replay the same match
+    pub seed: u64,
+    /// Map file to play on, relative to `assets`. Randomly generated maps are used when `None`
+    pub map: Option<String>,
+    /// Name of the `MapGenerator` to generate maps with, see `MAP_GENERATORS`. A mix of `gen_v2`
+    /// maps when `None`
+    pub generator: Option<String>,
+    /// Name of the `GameMode` to play, see `GAME_MODES`
+    pub mode: String,
+    /// Overrides the game mode's `RespawnRule`, unless the mode has no respawns
+    pub respawn: Option<crate::RespawnRule>,
+    /// Friendly fire, only matters in modes with teams
+    pub team_rules: crate::TeamRules,
+    /// Only keep the map chunks within this many world units of a tank or the camera loaded,
+    /// see `MapStreaming`. Every chunk is loaded when `None`
+    pub streaming: Option<f32>,
 }
 
-#[derive(Component)]
-struct AnimatedSpriteSheet {
-    first: usize,
-    last: usize,
-    timer: Timer,
-    despawn_on_end: bool,
+impl Default for MatchSettings {
+    fn default() -> Self {
+        Self {
+            human_player: true,
+            seed: 0,
+            map: None,
+            generator: None,
+            mode: "last-tank-standing".to_owned(),
+            respawn: None,
+            team_rules: crate::TeamRules::default(),
+            streaming: None,
+        }
+    }
 }
 
+/// The only source of randomness gameplay code may use, seeded from `MatchSettings::seed`
+#[derive(Resource)]
+pub struct GameRng(pub ChaChaRng);
+
+/// Gameplay and physics advance by this many seconds every `FixedUpdate`
+pub const DEFAULT_TIMESTEP: f32 = 1.0 / 60.0;
+
+/// Collision group of map tiles that block everything, see `Tile::blocks_bullets`
+pub const WALL_GROUP: Group = Group::GROUP_1;
+/// Collision group of map tiles that only block tanks, like water
+pub const TANK_BLOCKER_GROUP: Group = Group::GROUP_2;
+pub const TANK_GROUP: Group = Group::GROUP_3;
+pub const BULLET_GROUP: Group = Group::GROUP_4;
+
+/// Game logic, independent of any window or renderer
 pub struct TanksPlugin;
 
 impl Plugin for TanksPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(PreStartup, load_materials);
+        app.init_resource::<MatchSettings>();
+        app.init_resource::<crate::TeamRules>();
+        app.add_event::<ExplosionEvent>();
+        app.add_event::<crate::TankDestroyedEvent>();
+        app.add_systems(PreStartup, seed_game_rng);
         app.add_systems(PostStartup, start_game);
-        app.add_systems(Update, animate_sprite);
+        app.add_systems(
+            FixedUpdate,
+            (display_events, destroy_tanks)
+                .chain()
+                .after(PhysicsSet::Writeback),
+        );
 
         crate::init_tank_systems(app);
+        crate::init_controller_systems(app);
+        crate::init_bullet_systems(app);
+        crate::init_archetype_systems(app);
+        crate::init_map_file_systems(app);
+        crate::init_chunk_systems(app);
+        crate::init_destructible_systems(app);
+        crate::init_flow_systems(app);
+        crate::init_spawn_systems(app);
+        crate::init_respawn_systems(app);
+        crate::init_game_mode_systems(app);
+        crate::init_flag_systems(app);
+    }
+}
+
+/// Transform propagation at the start of every fixed update. Order against this rather than
+/// `propagate_transforms`, which Rapier also runs in `PhysicsSet::SyncBackend`
+#[derive(SystemSet, Clone, Debug, Hash, PartialEq, Eq)]
+pub struct FixedTransformPropagation;
+
+/// Applies the commands of everything that runs before physics, e.g. bullets fired this step.
+/// Entities spawned later only get their Rapier handles a step late, and a despawn applied in
+/// the same flush as that handle insert panics
+#[derive(SystemSet, Clone, Debug, Hash, PartialEq, Eq)]
+pub struct FlushBeforePhysics;
+
+/// Runs Rapier inside `FixedUpdate` right after the tanks have applied their inputs, so that
+/// physics and gameplay step together at `timestep` no matter the frame rate
+pub struct FixedPhysicsPlugin {
+    pub timestep: f32,
+}
+
+impl Default for FixedPhysicsPlugin {
+    fn default() -> Self {
+        Self {
+            timestep: DEFAULT_TIMESTEP,
+        }
+    }
+}
+
+impl Plugin for FixedPhysicsPlugin {
+    fn build(&self, app: &mut App) {
+        type Rapier = RapierPhysicsPlugin<NoUserData>;
+
+        app.add_plugins(
+            Rapier::default()
+                .with_physics_scale(1.0)
+                .with_default_system_setup(false),
+        );
+        app.insert_resource(FixedTime::new_from_secs(self.timestep));
+        app.insert_resource(RapierConfiguration {
+            timestep_mode: TimestepMode::Fixed {
+                dt: self.timestep,
+                substeps: 1,
+            },
+            ..Default::default()
+        });
+
+        app.configure_sets(
+            FixedUpdate,
+            (
+                crate::TankSystemSet::ApplyInputs,
+                FlushBeforePhysics,
+                PhysicsSet::SyncBackend,
+                PhysicsSet::SyncBackendFlush,
+                PhysicsSet::StepSimulation,
+                PhysicsSet::Writeback,
+            )
+                .chain(),
+        );
+        app.add_systems(
+            FixedUpdate,
+            (
+                apply_deferred.in_set(FlushBeforePhysics),
+                Rapier::get_systems(PhysicsSet::SyncBackend).in_set(PhysicsSet::SyncBackend),
+                Rapier::get_systems(PhysicsSet::SyncBackendFlush)
+                    .in_set(PhysicsSet::SyncBackendFlush),
+                Rapier::get_systems(PhysicsSet::StepSimulation).in_set(PhysicsSet::StepSimulation),
+                Rapier::get_systems(PhysicsSet::Writeback).in_set(PhysicsSet::Writeback),
+            ),
+        );
+
+        // Several fixed updates can run back to back in one frame without bevy's transform
+        // propagation in between, so propagate before controllers look at `GlobalTransform`s
+        app.add_systems(
+            FixedUpdate,
+            (sync_simple_transforms, propagate_transforms)
+                .chain()
+                .in_set(FixedTransformPropagation)
+                .before(crate::TankSystemSet::Controllers),
+        );
     }
 }
 
-fn load_materials(
+fn seed_game_rng(mut commands: Commands, settings: Res<MatchSettings>) {
+    commands.insert_resource(GameRng(ChaChaRng::seed_from_u64(settings.seed)));
+}
+
+fn start_game(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
-    mut texture_atlases: ResMut<Assets<TextureAtlas>>,
-    mut materials: ResMut<Assets<ColorMaterial>>,
+    settings: Res<MatchSettings>,
 ) {
-    let explosion_handle = asset_server.load("explosion_sheet.png");
-    let explosion_atlas =
-        TextureAtlas::from_grid(explosion_handle, Vec2::new(256., 256.), 8, 6, None, None);
-    let explosion = texture_atlases.add(explosion_atlas);
-
-    let wall_material = materials.add(ColorMaterial::from(Color::PURPLE));
-
-    commands.insert_resource(Materials {
-        bullet: asset_server.load("bullet.png"),
-        tank_base: asset_server.load("tank_base.png"),
-        tank_gun: asset_server.load("tank_gun.png"),
-        explosion,
-        wall_material,
+    let mode = crate::game_mode(&settings.mode).unwrap_or_else(|| {
+        panic!(
+            "Unknown game mode {}, expected one of {:?}",
+            settings.mode,
+            crate::GAME_MODES
+        )
     });
-}
 
-fn start_game(mut commands: Commands, materials: Res<Materials>, mut meshes: ResMut<Assets<Mesh>>) {
-    let mut projection = OrthographicProjection::default();
-    projection.scaling_mode = ScalingMode::FixedVertical(20.0);
-    projection.near = -1000.0;
-    projection.far = 1000.0;
+    let tanks = [
+        ("Troy", "medium", settings.human_player),
+        ("A.I", "scout", false),
+        ("A.I3", "heavy", false),
+        ("A.I2", "artillery", false),
+    ];
+    let roster = crate::Roster(
+        tanks
+            .into_iter()
+            .enumerate()
+            .map(
+                |(i, (name, archetype, player_controlled))| crate::RosterEntry {
+                    name: name.to_owned(),
+                    archetype: archetype.to_owned(),
+                    player_controlled,
+                    team: mode.team(i),
+                },
+            )
+            .collect(),
+    );
+    roster.spawn(&mut commands);
 
-    commands.spawn(Camera2dBundle {
-        projection,
-        ..Default::default()
-    });
+    let no_respawns = mode.respawn() == crate::RespawnRule::Never;
+    let respawn = match settings.respawn.clone() {
+        // Rounds of modes without respawns end when tanks run out, which respawns would prevent
+        Some(rule) if no_respawns && rule != crate::RespawnRule::Never => {
+            warn!(
+                "{} has no respawns, ignoring the respawn setting",
+                mode.name()
+            );
+            crate::RespawnRule::Never
+        }
+        Some(rule) => rule,
+        None => mode.respawn(),
+    };
+    commands.insert_resource(respawn);
+    commands.insert_resource(settings.team_rules.clone());
+    if let Some(radius) = settings.streaming {
+        commands.insert_resource(crate::MapStreaming { radius });
+    }
+    commands.insert_resource(crate::MatchState::new(&*mode));
+    commands.insert_resource(crate::ActiveGameMode(mode));
+    commands.insert_resource(roster);
 
-    crate::spawn_tank(
-        &mut commands,
-        &materials,
-        Vec2::new(2.0, 2.0),
-        "Troy".into(),
-        true,
-    );
+    if let Some(path) = &settings.map {
+        let map = crate::spawn_map_from_file(&mut commands, &asset_server, path, Vec2::ZERO);
+        commands.entity(map).insert(crate::ArenaMap);
+        return;
+    }
 
-    crate::spawn_tank(
-        &mut commands,
-        &materials,
-        Vec2::new(-5.0, 0.0),
-        "A.I".into(),
-        false,
-    );
-    crate::spawn_tank(
-        &mut commands,
-        &materials,
-        Vec2::new(2.0, -6.0),
-        "A.I3".into(),
-        false,
-    );
-    crate::spawn_tank(
-        &mut commands,
-        &materials,
-        Vec2::new(-5.0, 7.0),
-        "A.I2".into(),
-        false,
-    );
+    // Tanks only play on the first map, the others can't be driven to
+    let map_offsets = [
+        Vec2::new(0.0, 0.0),
+        Vec2::new(0.0, 11.0),
+        Vec2::new(0.0, -11.0),
+    ];
+    if let Some(name) = &settings.generator {
+        let generator = crate::map_generator(name).unwrap_or_else(|| {
+            panic!(
+                "Unknown map generator {name}, expected one of {:?}",
+                crate::MAP_GENERATORS
+            )
+        });
+        for (i, offset) in map_offsets.into_iter().enumerate() {
+            let tiles = generator.generate(IVec2::new(20, 10), 4, settings.seed ^ i as u64);
+            let map = commands
+                .spawn(crate::MapBundle::new_from_tiles(tiles, offset))
+                .id();
+            if i == 0 {
+                commands.entity(map).insert(crate::ArenaMap);
+            }
+        }
+        return;
+    }
 
-    let tiles = crate::MapTiles::gen_v1(IVec2::new(20, 10), 0.1, 0x42707564210);
-    //let a = tiles.astar(IVec2::new(1, 1), IVec2::new(7, 18)).unwrap();
-    //let astar = AStarBundle::new(&materials, &mut meshes, a);
-    //commands.spawn(astar);
+    let gen = |fullness, symmetry, seed| {
+        let options = crate::GenV2Options {
+            fullness,
+            symmetry,
+            ..Default::default()
+        };
+        crate::MapTiles::gen_v2(IVec2::new(20, 10), &options, settings.seed ^ seed)
+    };
 
-    let map = crate::MapBundle::new_from_tiles(&materials, &mut meshes, tiles, Vec2::new(0.0, 0.0));
-    commands.spawn(map);
+    let tiles = gen(0.1, crate::Symmetry::Rotational, 0x42707564210);
+    let map = crate::MapBundle::new_from_tiles(tiles, map_offsets[0]);
+    commands.spawn((map, crate::ArenaMap));
 
-    let tiles = crate::MapTiles::gen_v1(IVec2::new(20, 10), 0.5, 0x754620);
-    let map =
-        crate::MapBundle::new_from_tiles(&materials, &mut meshes, tiles, Vec2::new(0.0, 11.0));
+    let tiles = gen(0.5, crate::Symmetry::MirrorX, 0x754620);
+    let map = crate::MapBundle::new_from_tiles(tiles, map_offsets[1]);
     commands.spawn(map);
 
-    let tiles = crate::MapTiles::gen_v1(IVec2::new(20, 10), 1.0, 0x7546205420);
-    let map =
-        crate::MapBundle::new_from_tiles(&materials, &mut meshes, tiles, Vec2::new(0.0, -11.0));
+    let tiles = gen(1.0, crate::Symmetry::MirrorXY, 0x7546205420);
+    let map = crate::MapBundle::new_from_tiles(tiles, map_offsets[2]);
     commands.spawn(map);
 }
 
+/// Whether a bullet hitting a tank hurts it, and how much
+#[derive(SystemParam)]
+pub struct TankDamage<'w, 's> {
+    rules: Res<'w, crate::TeamRules>,
+    q_team: Query<'w, 's, &'static crate::Team>,
+    q_protected: Query<'w, 's, (), With<crate::SpawnProtection>>,
+    q_health: Query<'w, 's, (&'static mut crate::Health, &'static crate::Armor)>,
+}
+
 pub fn display_events(
     mut commands: Commands,
-    materials: Res<Materials>,
+    mut destroyed: EventWriter<crate::TankDestroyedEvent>,
+    mut explosions: EventWriter<ExplosionEvent>,
     mut collision_events: EventReader<CollisionEvent>,
     q_tank: Query<(&crate::TankBody, &Transform)>,
-    q_bullet: Query<&crate::Bullet>,
+    q_bullet: Query<(&crate::Bullet, &Transform)>,
+    mut damage: TankDamage,
 ) {
+    // A bullet touching two things in the same step only affects the first one
+    let mut spent_bullets = vec![];
+
     for event in collision_events.iter() {
         //println!("Received collision event: {event:?}");
         if let CollisionEvent::Started(a, b, _flags) = event {
-            if let Ok(((tank, tank_transform), bullet, tank_entity, bullet_entity)) =
+            // Bullets that hit each other cancel out
+            if let Ok(((_, a_transform), (_, b_transform), a, b)) =
+                query_dual_entities(*a, *b, &q_bullet, &q_bullet)
+            {
+                if spent_bullets.contains(&a) || spent_bullets.contains(&b) {
+                    continue;
+                }
+                spent_bullets.extend([a, b]);
+                commands.entity(a).despawn_recursive();
+                commands.entity(b).despawn_recursive();
+
+                let pos = (a_transform.translation + b_transform.translation).truncate() / 2.0;
+                explosions.send(crate::bullet_impact(pos));
+                continue;
+            }
+
+            if let Ok(((_, hit_transform), (bullet, bullet_transform), hit_entity, bullet_entity)) =
                 query_dual_entities(*a, *b, &q_tank, &q_bullet)
             {
-                if let Ok((shooter, _)) = q_tank.get(bullet.shooter) {
-                    println!("{} killed {}", shooter.name, tank.name);
+                if spent_bullets.contains(&bullet_entity) {
+                    continue;
                 }
-                commands.entity(tank_entity).despawn_recursive();
+                spent_bullets.push(bullet_entity);
                 commands.entity(bullet_entity).despawn_recursive();
+                explosions.send(crate::bullet_impact(
+                    bullet_transform.translation.truncate(),
+                ));
+
+                let hit_team = damage.q_team.get(hit_entity).ok().copied();
+                let Some(tank_entity) = damage
+                    .rules
+                    .damaged_tank((bullet.shooter, bullet.team), (hit_entity, hit_team))
+                else {
+                    continue;
+                };
+                let reflected = tank_entity != hit_entity;
+                // A reflected shot kills the shooter with nobody to credit
+                let killer =
+                    (!reflected && q_tank.contains(bullet.shooter)).then_some(bullet.shooter);
 
-                spawn_explosion(
-                    &mut commands,
-                    &materials,
-                    tank_transform.translation.truncate(),
-                    Duration::from_secs_f32(1.2),
-                )
+                if damage.q_protected.contains(tank_entity) {
+                    continue;
+                }
+
+                let Ok((tank, tank_transform)) = q_tank.get(tank_entity) else {
+                    continue;
+                };
+                let Ok((mut health, armor)) = damage.q_health.get_mut(tank_entity) else {
+                    continue;
+                };
+                // Already destroyed earlier this step
+                if health.is_dead() {
+                    continue;
+                }
+
+                // Reflected damage hits the shooter on the side facing its ally
+                let impact_pos = if reflected {
+                    hit_transform.translation.truncate()
+                } else {
+                    bullet_transform.translation.truncate()
+                };
+                crate::apply_damage(
+                    &mut health,
+                    armor,
+                    tank_transform,
+                    impact_pos,
+                    bullet.damage,
+                );
+
+                if health.is_dead() {
+                    destroyed.send(crate::TankDestroyedEvent {
+                        tank: tank_entity,
+                        name: tank.name.clone(),
+                        killer,
+                        pos: tank_transform.translation.truncate(),
+                    });
+                }
             }
         }
     }
 }
 
+pub fn destroy_tanks(
+    mut commands: Commands,
+    mut explosions: EventWriter<ExplosionEvent>,
+    mut destroyed: EventReader<crate::TankDestroyedEvent>,
+    q_tank: Query<&crate::TankBody>,
+) {
+    for event in destroyed.iter() {
+        if let Some(killer) = event.killer.and_then(|killer| q_tank.get(killer).ok()) {
+            println!("{} killed {}", killer.name, event.name);
+        }
+        commands.entity(event.tank).despawn_recursive();
+
+        explosions.send(ExplosionEvent {
+            pos: event.pos,
+            size: 1.5,
+            length: Duration::from_secs_f32(1.2),
+        });
+    }
+}
+
 fn query_dual_entities<'q, I1, I2, Q1, Q2>(
     a: Entity,
     b: Entity,
@@ -168,51 +446,3 @@ where
 
     Err(())
 }
-
-fn animate_sprite(
-    time: Res<Time>,
-    mut commands: Commands,
-    mut query: Query<(Entity, &mut AnimatedSpriteSheet, &mut TextureAtlasSprite)>,
-) {
-    for (entity, mut animation, mut sprite) in &mut query {
-        if animation.timer.tick(time.delta()).just_finished() {
-            sprite.index = if sprite.index == animation.last - 1 {
-                if animation.despawn_on_end {
-                    commands.entity(entity).despawn_recursive();
-                    animation.first
-                } else {
-                    animation.first
-                }
-            } else {
-                sprite.index + 1
-            };
-        }
-    }
-}
-
-fn spawn_explosion(
-    commands: &mut Commands,
-    materials: &Res<Materials>,
-    pos: Vec2,
-    length: Duration,
-) {
-    let frames = 6 * 8;
-    commands.spawn((
-        SpriteSheetBundle {
-            texture_atlas: materials.explosion.clone(),
-            sprite: TextureAtlasSprite {
-                index: 0,
-                custom_size: Some(Vec2::new(1.5, 1.5)),
-                ..Default::default()
-            },
-            transform: Transform::from_xyz(pos.x, pos.y, 0.0),
-            ..default()
-        },
-        AnimatedSpriteSheet {
-            first: 0,
-            last: frames,
-            timer: Timer::new(length / frames as u32, TimerMode::Repeating),
-            despawn_on_end: true,
-        },
-    ));
-}
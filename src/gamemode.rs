@@ -0,0 +1,495 @@
+use bevy::{ecs::system::SystemParam, prelude::*, utils::HashMap};
+use std::fmt;
+
+use crate::Team;
+
+/// Seconds between the end of a round and the start of the next
+pub const ROUND_INTERMISSION: f32 = 3.0;
+
+/// Who scores points and wins: a team in team modes, a tank by name otherwise
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub enum Side {
+    Team(Team),
+    Tank(String),
+}
+
+impl Side {
+    pub fn of(name: &str, team: Option<&Team>) -> Self {
+        match team {
+            Some(team) => Side::Team(*team),
+            None => Side::Tank(name.to_owned()),
+        }
+    }
+}
+
+impl fmt::Display for Side {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Side::Team(team) => write!(f, "{} team", team.name()),
+            Side::Tank(name) => write!(f, "{name}"),
+        }
+    }
+}
+
+/// What a `GameMode` knows when deciding whether a round is over
+pub struct RoundView<'a> {
+    pub scores: &'a HashMap<Side, i32>,
+    /// Sides with a tank alive or waiting to respawn
+    pub alive: &'a [Side],
+    /// The round's time limit has run out
+    pub time_up: bool,
+}
+
+/// The side with the most points, `None` when nobody has any or the lead is shared
+pub fn leader<N: Copy + Ord + Default>(scores: &HashMap<Side, N>) -> Option<Side> {
+    let best = scores.values().copied().max()?;
+    let mut leaders = scores.iter().filter(|(_, score)| **score == best);
+    let (side, _) = leaders.next()?;
+    (leaders.next().is_none() && best > N::default()).then(|| side.clone())
+}
+
+/// Rules of a match: who is on which team, how points are scored and when it ends
+pub trait GameMode: Send + Sync + 'static {
+    fn name(&self) -> &'static str;
+
+    /// Team of the `index`th tank in the `Roster`, `None` when every tank is on its own
+    fn team(&self, _index: usize) -> Option<Team> {
+        None
+    }
+
+    /// How destroyed tanks come back, unless `MatchSettings::respawn` says otherwise. Modes that
+    /// return `RespawnRule::Never` can't be overridden
+    fn respawn(&self) -> crate::RespawnRule;
+
+    /// Seconds each round lasts, `None` without a limit
+    fn time_limit(&self) -> Option<f32> {
+        None
+    }
+
+    /// Rounds in a match, the side that wins the most of them wins the match
+    fn rounds(&self) -> u32 {
+        1
+    }
+
+    /// Whether capture-the-flag flags are placed at the start of each round, see `Flag`
+    fn uses_flags(&self) -> bool {
+        false
+    }
+
+    /// Points `killer` scores for destroying a tank of `victim`. Destroying your own tank or a
+    /// teammate costs a point
+    fn kill_points(&self, killer: &Side, victim: &Side) -> i32 {
+        if killer == victim {
+            -1
+        } else {
+            1
+        }
+    }
+
+    /// `Some` with the winner, or `None` for a draw, once the round is over
+    fn round_over(&self, view: &RoundView) -> Option<Option<Side>>;
+}
+
+/// Names of the modes `game_mode` knows
+pub const GAME_MODES: [&str; 4] = [
+    "deathmatch",
+    "team-deathmatch",
+    "last-tank-standing",
+    "capture-the-flag",
+];
+
+/// The mode called `name` with its default settings, see `GAME_MODES`
+pub fn game_mode(name: &str) -> Option<Box<dyn GameMode>> {
+    Some(match name {
+        "deathmatch" => Box::new(Deathmatch::default()),
+        "team-deathmatch" => Box::new(TeamDeathmatch::default()),
+        "last-tank-standing" => Box::new(LastTankStanding::default()),
+        "capture-the-flag" => Box::new(CaptureTheFlag::default()),
+        _ => return None,
+    })
+}
+
+/// Ends the round once a side reaches `limit` points, or with the leader when time runs out
+fn score_limit_reached(view: &RoundView, limit: i32) -> Option<Option<Side>> {
+    if let Some((side, _)) = view.scores.iter().find(|(_, score)| **score >= limit) {
+        return Some(Some(side.clone()));
+    }
+    view.time_up.then(|| leader(view.scores))
+}
+
+/// Every tank for itself, first to `score_limit` kills wins
+#[derive(Clone, Debug)]
+pub struct Deathmatch {
+    pub score_limit: i32,
+    pub time_limit: f32,
+}
+
+impl Default for Deathmatch {
+    fn default() -> Self {
+        Self {
+            score_limit: 10,
+            time_limit: 300.0,
+        }
+    }
+}
+
+impl GameMode for Deathmatch {
+    fn name(&self) -> &'static str {
+        "Deathmatch"
+    }
+
+    fn respawn(&self) -> crate::RespawnRule {
+        crate::RespawnRule::Timed {
+            delay: crate::DEFAULT_RESPAWN_DELAY,
+        }
+    }
+
+    fn time_limit(&self) -> Option<f32> {
+        Some(self.time_limit)
+    }
+
+    fn round_over(&self, view: &RoundView) -> Option<Option<Side>> {
+        score_limit_reached(view, self.score_limit)
+    }
+}
+
+/// Tanks are split over `teams` teams, the first team to `score_limit` kills wins
+#[derive(Clone, Debug)]
+pub struct TeamDeathmatch {
+    pub teams: u8,
+    pub score_limit: i32,
+    pub time_limit: f32,
+}
+
+impl Default for TeamDeathmatch {
+    fn default() -> Self {
+        Self {
+            teams: 2,
+            score_limit: 15,
+            time_limit: 300.0,
+        }
+    }
+}
+
+impl GameMode for TeamDeathmatch {
+    fn name(&self) -> &'static str {
+        "Team deathmatch"
+    }
+
+    fn team(&self, index: usize) -> Option<Team> {
+        Some(Team((index % self.teams.max(1) as usize) as u8))
+    }
+
+    fn respawn(&self) -> crate::RespawnRule {
+        crate::RespawnRule::Timed {
+            delay: crate::DEFAULT_RESPAWN_DELAY,
+        }
+    }
+
+    fn time_limit(&self) -> Option<f32> {
+        Some(self.time_limit)
+    }
+
+    fn round_over(&self, view: &RoundView) -> Option<Option<Side>> {
+        score_limit_reached(view, self.score_limit)
+    }
+}
+
+/// No respawns, the last tank alive wins the round
+#[derive(Clone, Debug)]
+pub struct LastTankStanding {
+    pub rounds: u32,
+}
+
+impl Default for LastTankStanding {
+    fn default() -> Self {
+        Self { rounds: 1 }
+    }
+}
+
+impl GameMode for LastTankStanding {
+    fn name(&self) -> &'static str {
+        "Last tank standing"
+    }
+
+    fn respawn(&self) -> crate::RespawnRule {
+        crate::RespawnRule::Never
+    }
+
+    fn rounds(&self) -> u32 {
+        self.rounds
+    }
+
+    fn round_over(&self, view: &RoundView) -> Option<Option<Side>> {
+        (view.alive.len() <= 1).then(|| view.alive.first().cloned())
+    }
+}
+
+/// Two teams, each scores by bringing the other team's flag to its own
+#[derive(Clone, Debug)]
+pub struct CaptureTheFlag {
+    pub capture_limit: i32,
+    pub time_limit: f32,
+}
+
+impl Default for CaptureTheFlag {
+    fn default() -> Self {
+        Self {
+            capture_limit: 3,
+            time_limit: 600.0,
+        }
+    }
+}
+
+impl GameMode for CaptureTheFlag {
+    fn name(&self) -> &'static str {
+        "Capture the flag"
+    }
+
+    fn team(&self, index: usize) -> Option<Team> {
+        Some(Team((index % 2) as u8))
+    }
+
+    fn respawn(&self) -> crate::RespawnRule {
+        crate::RespawnRule::Timed {
+            delay: crate::DEFAULT_RESPAWN_DELAY,
+        }
+    }
+
+    fn time_limit(&self) -> Option<f32> {
+        Some(self.time_limit)
+    }
+
+    fn uses_flags(&self) -> bool {
+        true
+    }
+
+    /// Only captures score
+    fn kill_points(&self, _killer: &Side, _victim: &Side) -> i32 {
+        0
+    }
+
+    fn round_over(&self, view: &RoundView) -> Option<Option<Side>> {
+        score_limit_reached(view, self.capture_limit)
+    }
+}
+
+/// The mode the match is played with
+#[derive(Resource)]
+pub struct ActiveGameMode(pub Box<dyn GameMode>);
+
+/// A tank that takes part in every round
+#[derive(Clone, Debug)]
+pub struct RosterEntry {
+    pub name: String,
+    pub archetype: String,
+    pub player_controlled: bool,
+    pub team: Option<Team>,
+}
+
+/// Every tank in the match, spawned again at the start of each round
+#[derive(Clone, Debug, Default, Resource)]
+pub struct Roster(pub Vec<RosterEntry>);
+
+impl Roster {
+    pub fn spawn(&self, commands: &mut Commands) {
+        for entry in &self.0 {
+            crate::spawn_tank(
+                commands,
+                entry.name.clone(),
+                &entry.archetype,
+                entry.player_controlled,
+                entry.team,
+            );
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum MatchPhase {
+    Playing,
+    /// Everything is frozen until the next round starts
+    RoundOver {
+        winner: Option<Side>,
+        timer: Timer,
+    },
+    /// `None` on a draw
+    Finished {
+        winner: Option<Side>,
+    },
+}
+
+/// Progress of the match, gameplay only runs while `phase` is `MatchPhase::Playing`
+#[derive(Clone, Debug, Resource)]
+pub struct MatchState {
+    pub phase: MatchPhase,
+    /// Starts at 1
+    pub round: u32,
+    /// Time left in the round
+    pub round_timer: Option<Timer>,
+    /// Points scored this round
+    pub scores: HashMap<Side, i32>,
+    pub round_wins: HashMap<Side, u32>,
+}
+
+impl MatchState {
+    pub fn new(mode: &dyn GameMode) -> Self {
+        Self {
+            phase: MatchPhase::Playing,
+            round: 1,
+            round_timer: mode
+                .time_limit()
+                .map(|limit| Timer::from_seconds(limit, TimerMode::Once)),
+            scores: HashMap::default(),
+            round_wins: HashMap::default(),
+        }
+    }
+
+    pub fn add_points(&mut self, side: Side, points: i32) {
+        *self.scores.entry(side).or_default() += points;
+    }
+}
+
+/// Run condition for gameplay, which stops between rounds and once the match is over
+pub fn match_in_progress(state: Option<Res<MatchState>>) -> bool {
+    state.is_none_or(|state| state.phase == MatchPhase::Playing)
+}
+
+/// Scores kills for the mode. Runs before `destroy_tanks` despawns the destroyed tanks
+fn score_kills(
+    mode: Res<ActiveGameMode>,
+    mut state: ResMut<MatchState>,
+    mut destroyed: EventReader<crate::TankDestroyedEvent>,
+    q_tank: Query<(&crate::TankBody, Option<&Team>)>,
+) {
+    for event in destroyed.iter() {
+        let Ok((victim, victim_team)) = q_tank.get(event.tank) else {
+            continue;
+        };
+        let Some((killer, killer_team)) = event.killer.and_then(|k| q_tank.get(k).ok()) else {
+            continue;
+        };
+
+        let victim = Side::of(&victim.name, victim_team);
+        let killer = Side::of(&killer.name, killer_team);
+        let points = mode.0.kill_points(&killer, &victim);
+        state.add_points(killer, points);
+    }
+}
+
+/// Everything that is cleared away at the end of a round
+type RoundEntityFilter = Or<(
+    With<crate::TankBody>,
+    With<crate::Bullet>,
+    With<crate::Flag>,
+)>;
+
+/// Clears the arena and spawns the roster again for the next round
+#[derive(SystemParam)]
+struct RoundReset<'w, 's> {
+    commands: Commands<'w, 's>,
+    roster: Res<'w, Roster>,
+    respawns: ResMut<'w, crate::RespawnQueue>,
+    q_round_entities: Query<'w, 's, Entity, RoundEntityFilter>,
+}
+
+impl RoundReset<'_, '_> {
+    fn reset(&mut self) {
+        for entity in &self.q_round_entities {
+            self.commands.entity(entity).despawn_recursive();
+        }
+        self.respawns.0.clear();
+        self.roster.spawn(&mut self.commands);
+    }
+}
+
+fn update_match(
+    time: Res<FixedTime>,
+    mode: Res<ActiveGameMode>,
+    mut state: ResMut<MatchState>,
+    q_tank: Query<(&crate::TankBody, Option<&Team>)>,
+    mut round: RoundReset,
+) {
+    let state = &mut *state;
+    match &mut state.phase {
+        MatchPhase::Playing => {
+            let time_up = state
+                .round_timer
+                .as_mut()
+                .is_some_and(|timer| timer.tick(time.period).finished());
+
+            let mut alive: Vec<Side> = q_tank
+                .iter()
+                .map(|(tank, team)| Side::of(&tank.name, team))
+                .chain(
+                    round
+                        .respawns
+                        .0
+                        .iter()
+                        .map(|queued| Side::of(&queued.name, queued.team.as_ref())),
+                )
+                .collect();
+            alive.sort_by_key(|side| side.to_string());
+            alive.dedup();
+
+            let view = RoundView {
+                scores: &state.scores,
+                alive: &alive,
+                time_up,
+            };
+            let Some(winner) = mode.0.round_over(&view) else {
+                return;
+            };
+
+            if let Some(winner) = &winner {
+                *state.round_wins.entry(winner.clone()).or_default() += 1;
+                info!("{winner} wins round {}", state.round);
+            }
+            let majority = state
+                .round_wins
+                .values()
+                .any(|wins| *wins > mode.0.rounds() / 2);
+            state.phase = if state.round >= mode.0.rounds() || majority {
+                MatchPhase::Finished {
+                    winner: leader(&state.round_wins),
+                }
+            } else {
+                MatchPhase::RoundOver {
+                    winner,
+                    timer: Timer::from_seconds(ROUND_INTERMISSION, TimerMode::Once),
+                }
+            };
+        }
+        MatchPhase::RoundOver { timer, .. } => {
+            if !timer.tick(time.period).finished() {
+                return;
+            }
+
+            round.reset();
+
+            let round_wins = std::mem::take(&mut state.round_wins);
+            *state = MatchState {
+                round: state.round + 1,
+                round_wins,
+                ..MatchState::new(&*mode.0)
+            };
+        }
+        MatchPhase::Finished { .. } => {}
+    }
+}
+
+pub fn init_game_mode_systems(app: &mut App) {
+    app.add_systems(
+        FixedUpdate,
+        (
+            score_kills
+                .after(crate::display_events)
+                .before(crate::destroy_tanks),
+            // Round timers don't run while the round is still loading
+            update_match.after(crate::destroy_tanks).run_if(
+                not(any_with_component::<crate::MapLoading>())
+                    .and_then(not(any_with_component::<crate::PendingSpawn>())),
+            ),
+        ),
+    );
+}
@@ -0,0 +1,252 @@
+use std::time::{Duration, Instant};
+
+use bevy::{prelude::*, time::TimeUpdateStrategy};
+
+/// Settings for running matches without a window or renderer
+#[derive(Clone, Debug)]
+pub struct HeadlessConfig {
+    /// Simulated seconds per update, for both game logic and physics
+    pub timestep: f32,
+    /// Give up on the match after this many updates. `None` allows every round its full time
+    /// limit, see `HeadlessConfig::step_limit`
+    pub max_steps: Option<usize>,
+    /// See `MatchSettings::seed`
+    pub seed: u64,
+    /// See `MatchSettings::map`
+    pub map: Option<String>,
+    /// See `MatchSettings::generator`
+    pub generator: Option<String>,
+    /// See `MatchSettings::mode`
+    pub mode: String,
+    /// See `MatchSettings::respawn`
+    pub respawn: Option<crate::RespawnRule>,
+    /// See `MatchSettings::team_rules`
+    pub team_rules: crate::TeamRules,
+    /// See `MatchSettings::streaming`
+    pub streaming: Option<f32>,
+}
+
+/// Rounds without a time limit are given up on after this many seconds
+const UNTIMED_ROUND_LIMIT: f32 = 300.0;
+/// Extra seconds per round for loading and spawning, before the round timer starts
+const ROUND_SLACK: f32 = 10.0;
+
+impl HeadlessConfig {
+    /// Updates after which the match is given up on
+    pub fn step_limit(&self) -> usize {
+        if let Some(max_steps) = self.max_steps {
+            return max_steps;
+        }
+        let Some(mode) = crate::game_mode(&self.mode) else {
+            return (UNTIMED_ROUND_LIMIT / self.timestep) as usize;
+        };
+
+        let round = mode.time_limit().unwrap_or(UNTIMED_ROUND_LIMIT)
+            + crate::ROUND_INTERMISSION
+            + ROUND_SLACK;
+        (round * mode.rounds() as f32 / self.timestep).ceil() as usize
+    }
+}
+
+impl Default for HeadlessConfig {
+    fn default() -> Self {
+        Self {
+            timestep: crate::DEFAULT_TIMESTEP,
+            max_steps: None,
+            seed: 0,
+            map: None,
+            generator: None,
+            mode: crate::MatchSettings::default().mode,
+            respawn: None,
+            team_rules: crate::TeamRules::default(),
+            streaming: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MatchOutcome {
+    /// The tank or team that won according to the game mode, `None` on a draw or timeout
+    pub winner: Option<String>,
+    /// Names of all tanks alive at the end of the match
+    pub survivors: Vec<String>,
+    pub steps: usize,
+    /// Simulated (not wall clock) length of the match
+    pub duration: Duration,
+}
+
+/// Builds an app that runs the game logic and physics with no window, renderer or textures.
+/// Time advances by exactly `config.timestep` every `App::update`, running one `FixedUpdate`
+pub fn build_headless_app(config: &HeadlessConfig) -> App {
+    let mut app = App::new();
+    app.add_plugins((
+        MinimalPlugins,
+        AssetPlugin::default(),
+        TransformPlugin,
+        HierarchyPlugin,
+    ))
+    .add_plugins(crate::FixedPhysicsPlugin {
+        timestep: config.timestep,
+    })
+    .add_plugins(crate::TanksPlugin)
+    .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f32(
+        config.timestep,
+    )))
+    .insert_resource(crate::MatchSettings {
+        human_player: false,
+        seed: config.seed,
+        map: config.map.clone(),
+        generator: config.generator.clone(),
+        mode: config.mode.clone(),
+        respawn: config.respawn.clone(),
+        team_rules: config.team_rules.clone(),
+        streaming: config.streaming,
+    });
+
+    app.finish();
+    app.cleanup();
+    app
+}
+
+/// Steps `app` until the game mode finishes the match or `config.step_limit()` is reached
+pub fn run_headless_match(mut app: App, config: &HeadlessConfig) -> MatchOutcome {
+    let mut steps = 0;
+    let step_limit = config.step_limit();
+
+    let (winner, survivors) = loop {
+        app.update();
+        steps += 1;
+
+        let survivors: Vec<String> = app
+            .world
+            .query::<&crate::TankBody>()
+            .iter(&app.world)
+            .map(|tank| tank.name.clone())
+            .collect();
+        let finished = app
+            .world
+            .get_resource::<crate::MatchState>()
+            .and_then(|state| match &state.phase {
+                crate::MatchPhase::Finished { winner } => Some(winner.clone()),
+                _ => None,
+            });
+
+        if let Some(winner) = finished {
+            break (winner.map(|side| side.to_string()), survivors);
+        }
+        if steps >= step_limit {
+            break (None, survivors);
+        }
+    };
+
+    MatchOutcome {
+        winner,
+        survivors,
+        steps,
+        duration: Duration::from_secs_f32(config.timestep) * steps as u32,
+    }
+}
+
+/// Collider size and physics cost of one way of building a map's collider
+#[derive(Clone, Debug)]
+pub struct ColliderBenchResult {
+    /// Shapes in the map's compound collider
+    pub shapes: usize,
+    /// Mean wall clock time of one update, physics step included
+    pub step_time: Duration,
+}
+
+/// Times `steps` headless updates with `tiles` spawned next to the match's maps, built with a box
+/// per solid tile when `per_tile` is set, or with merged boxes otherwise
+pub fn bench_map_collider(
+    config: &HeadlessConfig,
+    tiles: &crate::MapTiles,
+    per_tile: bool,
+    steps: usize,
+) -> ColliderBenchResult {
+    let mut app = build_headless_app(config);
+
+    let (colliders, shapes) = if per_tile {
+        let solid = tiles.elements_row_major_iter().filter(|t| t.blocks_tanks());
+        (vec![tiles.build_per_tile_collider()], solid.count())
+    } else {
+        let colliders = tiles.build_colliders().into_iter().map(|(c, _)| c);
+        let shapes = tiles.wall_rects().len() + tiles.blocker_rects().len();
+        (colliders.collect(), shapes)
+    };
+    let offset = Vec2::new(-(tiles.num_columns() as f32) / 2.0, 20.0);
+    for collider in colliders {
+        app.world.spawn((
+            SpatialBundle::from_transform(Transform::from_translation(offset.extend(0.0))),
+            collider,
+        ));
+    }
+
+    // Let the first updates load assets and build the physics world before timing
+    for _ in 0..10 {
+        app.update();
+    }
+
+    let start = Instant::now();
+    for _ in 0..steps {
+        app.update();
+    }
+
+    ColliderBenchResult {
+        shapes,
+        step_time: start.elapsed() / steps as u32,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn match_runs_headless() {
+        let config = HeadlessConfig::default();
+        let outcome = run_headless_match(build_headless_app(&config), &config);
+
+        // The AI fights it out well within the time limit, and the last tank alive wins
+        assert!(outcome.steps < config.step_limit());
+        let winner = outcome.winner.expect("match finished without a winner");
+        assert_eq!(outcome.survivors, vec![winner]);
+    }
+
+    fn tank_transforms(app: &mut App) -> Vec<(String, Transform)> {
+        let mut tanks: Vec<_> = app
+            .world
+            .query::<(&crate::TankBody, &Transform)>()
+            .iter(&app.world)
+            .map(|(tank, transform)| (tank.name.clone(), *transform))
+            .collect();
+        tanks.sort_by(|a, b| a.0.cmp(&b.0));
+        tanks
+    }
+
+    #[test]
+    fn same_seed_replays_the_same() {
+        let config = HeadlessConfig {
+            seed: 7,
+            ..Default::default()
+        };
+        let mut a = build_headless_app(&config);
+        let mut b = build_headless_app(&config);
+
+        for step in 0..200 {
+            a.update();
+            b.update();
+            assert_eq!(
+                tank_transforms(&mut a),
+                tank_transforms(&mut b),
+                "diverged at step {step}"
+            );
+        }
+        assert!(!tank_transforms(&mut a).is_empty());
+
+        assert_eq!(
+            run_headless_match(a, &config),
+            run_headless_match(b, &config)
+        );
+    }
+}
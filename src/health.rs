@@ -0,0 +1,157 @@
+use bevy::prelude::*;
+use serde::Deserialize;
+use std::f32::consts::FRAC_PI_4;
+
+#[derive(Clone, Component, Debug)]
+pub struct Health {
+    pub current: f32,
+    pub max: f32,
+}
+
+impl Health {
+    pub fn new(max: f32) -> Self {
+        Self { current: max, max }
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.current <= 0.0
+    }
+}
+
+/// Which part of a tank's hull was hit, relative to the direction it is facing
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum HitSide {
+    Front,
+    Side,
+    Rear,
+}
+
+/// Multiplier applied to incoming damage depending on where the hull is hit
+#[derive(Clone, Component, Debug, Deserialize)]
+#[serde(default)]
+pub struct Armor {
+    pub front: f32,
+    pub side: f32,
+    pub rear: f32,
+}
+
+impl Default for Armor {
+    fn default() -> Self {
+        Self {
+            front: 0.5,
+            side: 1.0,
+            rear: 1.5,
+        }
+    }
+}
+
+impl Armor {
+    pub fn multiplier(&self, side: HitSide) -> f32 {
+        match side {
+            HitSide::Front => self.front,
+            HitSide::Side => self.side,
+            HitSide::Rear => self.rear,
+        }
+    }
+}
+
+/// Sent once when a tank's health reaches zero, before it is despawned
+#[derive(Clone, Debug, Event)]
+pub struct TankDestroyedEvent {
+    pub tank: Entity,
+    pub name: String,
+    /// The tank that fired the killing shot, if it is still alive
+    pub killer: Option<Entity>,
+    pub pos: Vec2,
+}
+
+/// Works out which side of a tank at `tank_transform` was hit by something at `impact_pos`
+pub fn hit_side(tank_transform: &Transform, impact_pos: Vec2) -> HitSide {
+    let delta = impact_pos - tank_transform.translation.truncate();
+    let impact_angle = f32::atan2(delta.y, delta.x);
+    let relative = crate::angle_delta(crate::get_rotz(tank_transform), impact_angle).abs();
+
+    if relative <= FRAC_PI_4 {
+        HitSide::Front
+    } else if relative >= 3.0 * FRAC_PI_4 {
+        HitSide::Rear
+    } else {
+        HitSide::Side
+    }
+}
+
+/// Applies `damage` to a tank hit at `impact_pos`, scaled by its armor.
+/// Returns the damage actually dealt
+pub fn apply_damage(
+    health: &mut Health,
+    armor: &Armor,
+    tank_transform: &Transform,
+    impact_pos: Vec2,
+    damage: f32,
+) -> f32 {
+    let dealt = damage * armor.multiplier(hit_side(tank_transform, impact_pos));
+    health.current = (health.current - dealt).max(0.0);
+    dealt
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::PI;
+
+    /// Just inside and outside of the boundary between two sides
+    const EPSILON: f32 = 0.01;
+
+    /// A tank at (3, -2) facing 1 radian, hit from `angle` radians off its facing
+    fn hit_at(angle: f32) -> HitSide {
+        let transform =
+            Transform::from_xyz(3.0, -2.0, 0.0).with_rotation(Quat::from_rotation_z(1.0));
+        let impact = Vec2::new(3.0, -2.0) + Vec2::from_angle(1.0 + angle) * 2.0;
+        hit_side(&transform, impact)
+    }
+
+    #[test]
+    fn hit_side_by_angle() {
+        assert_eq!(hit_at(0.0), HitSide::Front);
+        for sign in [1.0, -1.0] {
+            assert_eq!(hit_at(sign * (FRAC_PI_4 - EPSILON)), HitSide::Front);
+            assert_eq!(hit_at(sign * (FRAC_PI_4 + EPSILON)), HitSide::Side);
+            assert_eq!(hit_at(sign * PI / 2.0), HitSide::Side);
+            assert_eq!(hit_at(sign * (3.0 * FRAC_PI_4 - EPSILON)), HitSide::Side);
+            assert_eq!(hit_at(sign * (3.0 * FRAC_PI_4 + EPSILON)), HitSide::Rear);
+            assert_eq!(hit_at(sign * PI), HitSide::Rear);
+        }
+    }
+
+    #[test]
+    fn damage_is_scaled_by_armor() {
+        let armor = Armor {
+            front: 0.5,
+            side: 1.0,
+            rear: 2.0,
+        };
+        let transform = Transform::IDENTITY;
+        for (impact, dealt) in [(Vec2::X, 5.0), (Vec2::Y, 10.0), (-Vec2::X, 20.0)] {
+            let mut health = Health::new(100.0);
+            assert_eq!(
+                apply_damage(&mut health, &armor, &transform, impact, 10.0),
+                dealt
+            );
+            assert_eq!(health.current, 100.0 - dealt);
+        }
+    }
+
+    #[test]
+    fn health_stops_at_zero() {
+        let mut health = Health::new(10.0);
+        apply_damage(
+            &mut health,
+            &Armor::default(),
+            &Transform::IDENTITY,
+            -Vec2::X,
+            100.0,
+        );
+        assert_eq!(health.current, 0.0);
+        assert!(health.is_dead());
+    }
+}
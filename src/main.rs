@@ -1,5 +1,9 @@
 use bevy::prelude::*;
-use bevy_rapier2d::prelude::*;
+
+#[cfg(feature = "hot_reload")]
+use bevy::asset::ChangeWatcher;
+#[cfg(feature = "hot_reload")]
+use std::time::Duration;
 
 mod tank;
 pub use tank::*;
@@ -13,16 +17,210 @@ pub use game::*;
 mod map;
 pub use map::*;
 
+mod map_file;
+pub use map_file::*;
+
+mod chunk;
+pub use chunk::*;
+
+mod destructible;
+pub use destructible::*;
+
+mod mapgen;
+pub use mapgen::*;
+
+mod nav;
+pub use nav::*;
+
+mod flow;
+pub use flow::*;
+
+mod follow;
+pub use follow::*;
+
+mod spawn;
+pub use spawn::*;
+
+mod respawn;
+pub use respawn::*;
+
+mod team;
+pub use team::*;
+
+mod gamemode;
+pub use gamemode::*;
+
+mod flag;
+pub use flag::*;
+
+mod health;
+pub use health::*;
+
+mod archetype;
+pub use archetype::*;
+
+mod controller;
+pub use controller::*;
+
+mod ai;
+pub use ai::*;
+
+mod render;
+pub use render::*;
+
+mod debug;
+pub use debug::*;
+
+mod headless;
+pub use headless::*;
+
 fn main() {
-    let debug_physics = false;
-    let mut app = App::new();
-    app.add_plugins((DefaultPlugins, TanksPlugin))
-        .add_plugins((RapierPhysicsPlugin::<NoUserData>::default().with_physics_scale(1.0),))
-        .add_systems(PostUpdate, game::display_events);
+    let mut args = std::env::args().skip(1);
+    let mut headless = false;
+    let mut matches = 1;
+    let mut map = None;
+    let mut generator = None;
+    let mut respawn = None;
+    let mut mode = MatchSettings::default().mode;
+    let mut team_rules = TeamRules::default();
+    let mut bench_map = false;
+    let mut export_map = None;
+    let mut streaming = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--headless" => headless = true,
+            "--matches" => {
+                matches = args
+                    .next()
+                    .and_then(|n| n.parse().ok())
+                    .expect("--matches requires a number");
+            }
+            "--bench-map" => bench_map = true,
+            "--stream" => {
+                streaming = Some(
+                    args.next()
+                        .and_then(|r| r.parse().ok())
+                        .expect("--stream requires a radius"),
+                );
+            }
+            "--export-map" => {
+                export_map = Some(args.next().expect("--export-map requires a path"));
+            }
+            "--map" => map = Some(args.next().expect("--map requires a path")),
+            "--lives" => {
+                let lives = args
+                    .next()
+                    .and_then(|n| n.parse().ok())
+                    .expect("--lives requires a number");
+                respawn = Some(RespawnRule::Lives {
+                    lives,
+                    delay: DEFAULT_RESPAWN_DELAY,
+                });
+            }
+            "--respawn" => {
+                respawn = Some(RespawnRule::Timed {
+                    delay: DEFAULT_RESPAWN_DELAY,
+                })
+            }
+            "--mode" => {
+                let name = args.next().expect("--mode requires a name");
+                if game_mode(&name).is_none() {
+                    panic!("Unknown game mode {name}, expected one of {GAME_MODES:?}");
+                }
+                mode = name;
+            }
+            "--friendly-fire" => {
+                let name = args
+                    .next()
+                    .expect("--friendly-fire requires off, damage or reflect");
+                team_rules.friendly_fire = FriendlyFire::from_name(&name)
+                    .unwrap_or_else(|| panic!("Unknown friendly fire rule {name}"));
+            }
+            "--pass-allies" => team_rules.bullets_pass_allies = true,
+            "--generator" => {
+                let name = args.next().expect("--generator requires a name");
+                if map_generator(&name).is_none() {
+                    panic!("Unknown map generator {name}, expected one of {MAP_GENERATORS:?}");
+                }
+                generator = Some(name);
+            }
+            _ => panic!("Unknown argument {arg}"),
+        }
+    }
 
-    if debug_physics {
-        app.add_plugins(RapierDebugRenderPlugin::default());
+    if bench_map {
+        let config = HeadlessConfig::default();
+        let tiles = MapTiles::gen_v1(IVec2::new(200, 200), 0.5, 0);
+        for per_tile in [true, false] {
+            let result = bench_map_collider(&config, &tiles, per_tile, 300);
+            println!(
+                "{}: {} shapes, {:.3}ms per step",
+                if per_tile { "per tile" } else { "merged" },
+                result.shapes,
+                result.step_time.as_secs_f64() * 1000.0
+            );
+        }
+        return;
     }
 
+    // Writes a generated map to a map file, as a starting point for a hand made one
+    if let Some(path) = export_map {
+        let name = generator.as_deref().unwrap_or("v2");
+        let tiles = map_generator(name)
+            .unwrap_or_else(|| panic!("Unknown map generator {name}"))
+            .generate(IVec2::new(20, 10), 4, MatchSettings::default().seed);
+        std::fs::write(&path, tiles.serialize()).expect("Failed to write map file");
+        println!("Wrote a {name} map to {path}");
+        return;
+    }
+
+    if headless {
+        for i in 0..matches {
+            let config = HeadlessConfig {
+                seed: i,
+                map: map.clone(),
+                generator: generator.clone(),
+                mode: mode.clone(),
+                respawn: respawn.clone(),
+                team_rules: team_rules.clone(),
+                streaming,
+                ..Default::default()
+            };
+            let outcome = run_headless_match(build_headless_app(&config), &config);
+            println!(
+                "match {i}: winner: {:?}, survivors: {:?}, steps: {}, duration: {:.1}s",
+                outcome.winner,
+                outcome.survivors,
+                outcome.steps,
+                outcome.duration.as_secs_f32()
+            );
+        }
+        return;
+    }
+
+    let asset_plugin = AssetPlugin {
+        #[cfg(feature = "hot_reload")]
+        watch_for_changes: ChangeWatcher::with_delay(Duration::from_millis(200)),
+        ..default()
+    };
+
+    let mut app = App::new();
+    app.add_plugins((
+        DefaultPlugins.set(asset_plugin),
+        TanksPlugin,
+        TanksRenderPlugin,
+        TanksDebugPlugin,
+    ))
+    .add_plugins(FixedPhysicsPlugin::default())
+    .insert_resource(MatchSettings {
+        map,
+        generator,
+        mode,
+        respawn,
+        team_rules,
+        streaming,
+        ..Default::default()
+    });
+
     app.run();
 }
@@ -1,16 +1,18 @@
 use array2d::Array2D;
 use bevy::{
     prelude::*,
+    reflect::{TypePath, TypeUuid},
     render::{mesh::Indices, render_resource::PrimitiveTopology},
-    sprite::MaterialMesh2dBundle,
 };
 use bevy_rapier2d::prelude::*;
 use rand::{Rng, SeedableRng};
 
-#[derive(Clone, Bundle)]
+/// A map's colliders and meshes live on its `MapChunk` children, spawned by `sync_map_chunks`
+#[derive(Bundle)]
 pub struct MapBundle {
-    collider: Collider,
-    mesh: MaterialMesh2dBundle<ColorMaterial>,
+    tiles: MapTiles,
+    chunks: crate::MapChunks,
+    spatial: SpatialBundle,
     map: Map,
 }
 
@@ -18,85 +20,296 @@ pub struct MapBundle {
 pub struct Map;
 
 impl MapBundle {
-    pub fn new_empty(
-        materials: &Res<crate::Materials>,
-        meshes: &mut ResMut<Assets<Mesh>>,
-        size: IVec2,
-        world_offset: Vec2,
-    ) -> Self {
-        Self::new_from_tiles(materials, meshes, MapTiles::new_empty(size), world_offset)
-    }
-
-    pub fn new_from_tiles(
-        materials: &Res<crate::Materials>,
-        meshes: &mut ResMut<Assets<Mesh>>,
-        tiles: MapTiles,
-        world_offset: Vec2,
-    ) -> Self {
+    pub fn new_empty(size: IVec2, world_offset: Vec2) -> Self {
+        Self::new_from_tiles(MapTiles::new_empty(size), world_offset)
+    }
+
+    pub fn new_from_tiles(tiles: MapTiles, world_offset: Vec2) -> Self {
+        Self {
+            tiles,
+            chunks: crate::MapChunks::default(),
+            spatial: SpatialBundle::from_transform(Transform::from_xyz(
+                world_offset.x,
+                world_offset.y,
+                0.0,
+            )),
+            map: Map,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+pub enum Tile {
+    Air,
+    Wall,
+    /// A wall that bullets damage, it turns into `Air` when `hp` reaches zero
+    BreakableWall {
+        hp: u8,
+    },
+    /// Blocks tanks but not bullets
+    Water,
+    /// Floor that slows tanks down
+    Mud,
+    /// Floor that tanks slide around on
+    Ice,
+    /// A low wall that blocks tanks but not bullets
+    Barrier,
+}
+
+/// Hit points of a new `Tile::BreakableWall`
+pub const BREAKABLE_WALL_HP: u8 = 100;
+
+impl Tile {
+    /// Whether tanks collide with this tile
+    pub fn blocks_tanks(self) -> bool {
+        !matches!(self, Tile::Air | Tile::Mud | Tile::Ice)
+    }
+
+    /// Whether bullets collide with, and bounce off, this tile
+    pub fn blocks_bullets(self) -> bool {
+        matches!(self, Tile::Wall | Tile::BreakableWall { .. })
+    }
+
+    /// Multiplier for the top speed of tanks driving over this tile
+    pub fn speed_scale(self) -> f32 {
+        match self {
+            Tile::Mud => 0.5,
+            _ => 1.0,
+        }
+    }
+
+    /// Multiplier for the braking and damping of tanks driving over this tile. Below 1 tanks
+    /// take time to change direction
+    pub fn grip(self) -> f32 {
+        match self {
+            Tile::Ice => 0.15,
+            _ => 1.0,
+        }
+    }
+
+    /// Cost of driving across this tile for `MapTiles::astar`, `None` when tanks can't
+    pub fn path_cost(self) -> Option<usize> {
+        if self.blocks_tanks() {
+            return None;
+        }
+        Some(match self {
+            Tile::Mud => 3,
+            Tile::Ice => 2,
+            _ => 1,
+        })
+    }
+
+    /// Vertex color of the tile in map meshes, breakable walls fade as they are damaged
+    pub fn color(self) -> Color {
+        match self {
+            Tile::Air => Color::NONE,
+            Tile::Wall => Color::PURPLE,
+            Tile::Water => Color::rgb(0.15, 0.35, 0.8),
+            Tile::Mud => Color::rgb(0.3, 0.22, 0.12),
+            Tile::Ice => Color::rgb(0.75, 0.9, 0.95),
+            Tile::Barrier => Color::GRAY,
+            Tile::BreakableWall { hp } => {
+                let broken = Vec4::from(Color::rgb(0.35, 0.25, 0.2).as_rgba_f32());
+                let intact = Vec4::from(Color::rgb(0.8, 0.55, 0.3).as_rgba_f32());
+                Color::from(broken.lerp(intact, hp as f32 / BREAKABLE_WALL_HP as f32))
+            }
+        }
+    }
+}
+
+/// The tiles of one map, loadable from a `*.map` file (see `MapTiles::parse`)
+#[derive(Clone, Component, Debug, PartialEq, TypeUuid, TypePath)]
+#[uuid = "b7e2f0a4-3c6d-4e1b-9f25-8d4a6c1e7b30"]
+pub struct MapTiles {
+    tiles: Array2D<Tile>,
+    /// Where tanks may be spawned, in tile coordinates
+    pub spawn_points: Vec<IVec2>,
+}
+
+impl MapTiles {
+    /// Covers every tile `group` returns `Some` for with as few non-overlapping rectangles as a
+    /// greedy sweep finds, only merging tiles in equal groups. Each rectangle is
+    /// `(min, size, group)` in tiles, going up and right from `min`
+    fn merged_rects<K: PartialEq>(
+        &self,
+        group: impl Fn(Tile) -> Option<K>,
+    ) -> Vec<(IVec2, IVec2, K)> {
+        let rows = self.tiles.num_rows();
+        let columns = self.tiles.num_columns();
+        let mut covered = Array2D::filled_with(false, rows, columns);
+
+        let mut rects = vec![];
+        for y in 0..rows {
+            for x in 0..columns {
+                if covered[(y, x)] {
+                    continue;
+                }
+                let Some(key) = group(self.tiles[(y, x)]) else {
+                    continue;
+                };
+                let free = |covered: &Array2D<bool>, y: usize, x: usize| {
+                    !covered[(y, x)] && group(self.tiles[(y, x)]).as_ref() == Some(&key)
+                };
+
+                // Grow right as far as possible, then up while the whole width still matches
+                let mut width = 1;
+                while x + width < columns && free(&covered, y, x + width) {
+                    width += 1;
+                }
+                let mut height = 1;
+                while y + height < rows && (x..x + width).all(|i| free(&covered, y + height, i)) {
+                    height += 1;
+                }
+
+                for cy in y..y + height {
+                    for cx in x..x + width {
+                        covered[(cy, cx)] = true;
+                    }
+                }
+                rects.push((
+                    IVec2::new(x as i32, y as i32),
+                    IVec2::new(width as i32, height as i32),
+                    key,
+                ));
+            }
+        }
+        rects
+    }
+
+    /// Covers every tile matching `filter` with as few non-overlapping rectangles as a greedy
+    /// sweep finds. Each rectangle is `(min, size)` in tiles, going up and right from `min`
+    pub fn solid_rects(&self, filter: impl Fn(Tile) -> bool) -> Vec<(IVec2, IVec2)> {
+        self.merged_rects(|tile| filter(tile).then_some(()))
+            .into_iter()
+            .map(|(min, size, ())| (min, size))
+            .collect()
+    }
+
+    /// Rectangles of the tiles that block bullets, see `solid_rects`
+    pub fn wall_rects(&self) -> Vec<(IVec2, IVec2)> {
+        self.solid_rects(Tile::blocks_bullets)
+    }
+
+    /// Rectangles of the tiles that block tanks but not bullets, see `solid_rects`
+    pub fn blocker_rects(&self) -> Vec<(IVec2, IVec2)> {
+        self.solid_rects(|tile| tile.blocks_tanks() && !tile.blocks_bullets())
+    }
+
+    /// Builds a compound collider with one box per rectangle, `None` when there are none
+    fn rects_collider(rects: Vec<(IVec2, IVec2)>) -> Option<Collider> {
+        if rects.is_empty() {
+            return None;
+        }
+        let shapes: Vec<(Vect, Rot, Collider)> = rects
+            .into_iter()
+            .map(|(min, size)| {
+                let half = size.as_vec2() / 2.0;
+                (min.as_vec2() + half, 0.0, Collider::cuboid(half.x, half.y))
+            })
+            .collect();
+
+        Some(Collider::compound(shapes))
+    }
+
+    /// Builds the colliders for the walls and for the tiles only tanks collide with, in map
+    /// local coordinates
+    pub fn build_colliders(&self) -> Vec<(Collider, CollisionGroups)> {
+        let walls = Self::rects_collider(self.wall_rects()).map(|collider| {
+            (
+                collider,
+                CollisionGroups::new(crate::WALL_GROUP, Group::ALL),
+            )
+        });
+        let blockers = Self::rects_collider(self.blocker_rects()).map(|collider| {
+            (
+                collider,
+                CollisionGroups::new(crate::TANK_BLOCKER_GROUP, crate::TANK_GROUP),
+            )
+        });
+        walls.into_iter().chain(blockers).collect()
+    }
+
+    /// Builds a compound collider with one square per tile tanks collide with. Only kept to
+    /// compare against `build_colliders` in `bench_map_collider`
+    pub fn build_per_tile_collider(&self) -> Collider {
+        let mut shapes: Vec<(Vect, Rot, Collider)> = vec![];
+
+        for ((y, x), tile) in self.tiles.enumerate_row_major() {
+            if tile.blocks_tanks() {
+                let pos = Vec2::new(x as f32, y as f32);
+                shapes.push((pos + Vec2::new(0.5, 0.5), 0.0, Collider::cuboid(0.5, 0.5)));
+            }
+        }
+
+        Collider::compound(shapes)
+    }
+
+    pub fn from_tiles(tiles: Array2D<Tile>) -> Self {
+        Self {
+            tiles,
+            spawn_points: vec![],
+        }
+    }
+
+    /// Copies the tiles from `min` to `min + size`, clamped to the map. Spawn points are not
+    /// copied
+    pub fn sub_map(&self, min: IVec2, size: IVec2) -> MapTiles {
+        let bounds = self.size();
+        let min = min.clamp(IVec2::ZERO, bounds);
+        let max = (min + size).min(bounds);
+
+        let rows: Vec<Vec<Tile>> = (min.y..max.y)
+            .map(|y| {
+                (min.x..max.x)
+                    .map(|x| self.tiles[(y as usize, x as usize)])
+                    .collect()
+            })
+            .collect();
+        Self::from_tiles(Array2D::from_rows(&rows).expect("rows are all the same length"))
+    }
+
+    /// Builds a mesh with one quad per rectangle of identical non-air tiles, colored with
+    /// `Tile::color`, in map local coordinates
+    pub fn build_mesh(&self) -> Mesh {
         let mut triangle = Mesh::new(PrimitiveTopology::TriangleList);
 
         let mut verticies = vec![];
+        let mut colors = vec![];
         let mut indices = vec![];
-        let mut shapes: Vec<(Vect, Rot, Collider)> = vec![];
 
-        let mut add_square = |pos: Vec2| {
+        let mut add_rect = |pos: Vec2, size: Vec2, color: Color| {
             let i = verticies.len() as u32;
 
-            verticies.push([pos.x + 1.0, pos.y + 1.0, 0.0]);
-            verticies.push([pos.x + 0.0, pos.y + 1.0, 0.0]);
-            verticies.push([pos.x + 1.0, pos.y + 0.0, 0.0]);
+            verticies.push([pos.x + size.x, pos.y + size.y, 0.0]);
+            verticies.push([pos.x + 0.0, pos.y + size.y, 0.0]);
+            verticies.push([pos.x + size.x, pos.y + 0.0, 0.0]);
             verticies.push([pos.x + 0.0, pos.y + 0.0, 0.0]);
+            colors.extend([color.as_rgba_f32(); 4]);
 
-            indices.push(i + 0);
+            indices.push(i);
             indices.push(i + 1);
             indices.push(i + 2);
 
             indices.push(i + 2);
             indices.push(i + 1);
             indices.push(i + 3);
-
-            shapes.push((pos + Vec2::new(0.5, 0.5), 0.0, Collider::cuboid(0.5, 0.5)));
         };
 
-        for ((y, x), tile) in tiles.0.enumerate_row_major() {
-            match tile {
-                Tile::Air => {}
-                Tile::Wall => {
-                    add_square(Vec2::new(x as f32, y as f32));
-                }
-            }
+        for (min, size, tile) in self.merged_rects(|tile| (tile != Tile::Air).then_some(tile)) {
+            add_rect(min.as_vec2(), size.as_vec2(), tile.color());
         }
 
         triangle.insert_attribute(Mesh::ATTRIBUTE_POSITION, verticies.clone());
+        triangle.insert_attribute(Mesh::ATTRIBUTE_COLOR, colors);
         /*triangle.insert_attribute(
             Mesh::ATTRIBUTE_UV_0,
             vec![[0.0, 1.0], [1.0, 0.0], [1.0, 1.0]],
         );*/
 
         triangle.set_indices(Some(Indices::U32(indices.clone())));
-
-        Self {
-            collider: Collider::compound(shapes),
-            mesh: MaterialMesh2dBundle {
-                mesh: meshes.add(triangle).into(),
-                transform: Transform::from_xyz(world_offset.x, world_offset.y, 0.0),
-                material: materials.wall_material.clone(),
-                ..Default::default()
-            },
-            map: Map,
-        }
+        triangle
     }
-}
 
-#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
-pub enum Tile {
-    Air,
-    Wall,
-}
-
-pub struct MapTiles(Array2D<Tile>);
-
-impl MapTiles {
     pub fn new_empty(size: IVec2) -> Self {
         let mut map = Array2D::filled_with(Tile::Air, size.y as usize, size.x as usize);
         for i in 0..size.x as usize {
@@ -107,7 +320,7 @@ impl MapTiles {
             map[(i, 0)] = Tile::Wall;
             map[(i, size.x as usize - 1)] = Tile::Wall;
         }
-        Self(map)
+        Self::from_tiles(map)
     }
 
     /// Randomly generates a map based on `seed`.
@@ -121,7 +334,7 @@ impl MapTiles {
 
         // the number of walls we can spawn
         let spawnable_walls = (wanted_filled_tiles / MAX_WALL_LENGTH as f32).round() as usize;
-        dbg!(wanted_filled_tiles, spawnable_walls);
+        debug!("Spawning {spawnable_walls} walls to fill {wanted_filled_tiles} tiles");
 
         let spawn_tries = 1000;
         'spawn_loop: for i in 0..spawnable_walls {
@@ -161,55 +374,105 @@ impl MapTiles {
                 continue 'spawn_loop;
             }
 
-            println!("Failed to add wall {i}/{spawnable_walls} after {spawn_tries}");
+            debug!("Failed to add wall {i}/{spawnable_walls} after {spawn_tries} tries");
             break;
         }
 
+        // Scatter round patches of terrain over the open floor
+        const MAX_PATCH_RADIUS: i32 = 2;
+        let patches = (size.x * size.y) as usize / 60;
+        for _ in 0..patches {
+            let terrain = match rng.gen_range(0..3) {
+                0 => Tile::Water,
+                1 => Tile::Mud,
+                2 => Tile::Ice,
+                _ => unreachable!(),
+            };
+            let radius = rng.gen_range(1..=MAX_PATCH_RADIUS);
+            let center = IVec2::new(rng.gen_range(0..size.x), rng.gen_range(0..size.y));
+
+            for y in center.y - radius..=center.y + radius {
+                for x in center.x - radius..=center.x + radius {
+                    if (IVec2::new(x, y) - center).length_squared() > radius * radius {
+                        continue;
+                    }
+                    if let Some(t) = map.try_get_mut(IVec2::new(x, y)) {
+                        if *t == Tile::Air {
+                            *t = terrain;
+                        }
+                    }
+                }
+            }
+        }
+
         map
     }
 
+    /// Width and height in tiles. Tiles are addressed `(x, y)`, stored as `(row = y, column = x)`
+    pub fn size(&self) -> IVec2 {
+        IVec2::new(
+            self.tiles.num_columns() as i32,
+            self.tiles.num_rows() as i32,
+        )
+    }
+
+    pub fn in_bounds(&self, p: IVec2) -> bool {
+        p.cmpge(IVec2::ZERO).all() && p.cmplt(self.size()).all()
+    }
+
+    /// The tile containing world position `pos`, for a map with its origin (the `world_offset`
+    /// of its `MapBundle`) at `map_pos`. Not bounds checked
+    pub fn world_to_tile(map_pos: Vec2, pos: Vec2) -> IVec2 {
+        (pos - map_pos).floor().as_ivec2()
+    }
+
+    /// World position of the center of tile `p`, for a map with its origin at `map_pos`
+    pub fn tile_to_world(map_pos: Vec2, p: IVec2) -> Vec2 {
+        map_pos + p.as_vec2() + Vec2::splat(0.5)
+    }
+
+    /// The tile under world position `pos`, for this map placed with its origin at `map_pos`
+    pub fn tile_at_world(&self, map_pos: Vec2, pos: Vec2) -> Option<Tile> {
+        self.try_get(Self::world_to_tile(map_pos, pos)).copied()
+    }
+
     pub fn try_get(&self, p: IVec2) -> Option<&Tile> {
-        if p.x < 0 || p.x >= self.0.column_len() as i32 {
+        if !self.in_bounds(p) {
             return None;
         }
-        if p.y < 0 || p.y >= self.0.row_len() as i32 {
-            return None;
-        }
-
-        Some(&self.0[(p.x as usize, p.y as usize)])
+        self.tiles.get(p.y as usize, p.x as usize)
     }
 
     pub fn try_get_mut(&mut self, p: IVec2) -> Option<&mut Tile> {
-        if p.x < 0 || p.x >= self.0.column_len() as i32 {
-            return None;
-        }
-        if p.y < 0 || p.y >= self.0.column_len() as i32 {
+        if !self.in_bounds(p) {
             return None;
         }
+        self.tiles.get_mut(p.y as usize, p.x as usize)
+    }
 
-        Some(&mut self.0[(p.x as usize, p.y as usize)])
+    /// Every tile position on the map, row by row
+    pub fn positions(&self) -> impl Iterator<Item = IVec2> {
+        let size = self.size();
+        (0..size.y).flat_map(move |y| (0..size.x).map(move |x| IVec2::new(x, y)))
     }
 
-    pub fn astar(&self, start: IVec2, goal: IVec2) -> Result<AStarPath, ()> {
-        let success = |c: &IVec2| c == &goal;
-        let successors = |c: &IVec2| {
-            dbg!(c);
-            let mut v = smallvec::SmallVec::<[(IVec2, usize); 8]>::new();
-            let points = [IVec2::X, -IVec2::X, IVec2::Y, -IVec2::Y];
-            for p in points {
-                let pos = p + *c;
-                if let Some(t) = self.try_get(pos) {
-                    if t == &Tile::Air {
-                        v.push((pos, 1));
-                    }
-                }
-            }
-            v.into_iter()
-        };
-        let heuristic = |c: &IVec2| (c.as_vec2() - goal.as_vec2()).length().round() as usize;
-        pathfinding::directed::astar::astar(&start, successors, heuristic, success)
-            .map(|(path, cost)| AStarPath { path, cost })
-            .ok_or(())
+    /// The up to 8 tiles around `p` that are on the map
+    pub fn neighbours(&self, p: IVec2) -> impl Iterator<Item = IVec2> + '_ {
+        crate::NEIGHBOURS
+            .iter()
+            .map(move |step| p + *step)
+            .filter(|n| self.in_bounds(*n))
+    }
+
+    /// Finds the cheapest tile path from `start` to `goal`, see `NavGrid::astar`. Build a
+    /// `NavGrid` once instead when searching the same map many times
+    pub fn astar(
+        &self,
+        start: IVec2,
+        goal: IVec2,
+        options: &crate::PathOptions,
+    ) -> Option<AStarPath> {
+        crate::NavGrid::new(self, options).astar(start, goal)
     }
 }
 
@@ -217,13 +480,13 @@ impl std::ops::Deref for MapTiles {
     type Target = Array2D<Tile>;
 
     fn deref(&self) -> &Self::Target {
-        &self.0
+        &self.tiles
     }
 }
 
 impl std::ops::DerefMut for MapTiles {
     fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.0
+        &mut self.tiles
     }
 }
 
@@ -233,40 +496,106 @@ pub struct AStarPath {
     pub cost: usize,
 }
 
-/*
-#[derive(Clone, Bundle)]
-pub struct AStarBundle {
-    path: AStarPath,
-    mesh: MaterialMesh2dBundle<ColorMaterial>,
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZES: [IVec2; 6] = [
+        IVec2::new(20, 10),
+        IVec2::new(10, 20),
+        IVec2::new(1, 7),
+        IVec2::new(7, 1),
+        IVec2::new(1, 1),
+        IVec2::new(3, 3),
+    ];
+
+    /// A map of `size` where every tile is different, so mixed up coordinates show
+    fn numbered(size: IVec2) -> MapTiles {
+        let mut tiles = MapTiles::from_tiles(Array2D::filled_with(
+            Tile::Air,
+            size.y as usize,
+            size.x as usize,
+        ));
+        for p in tiles.positions() {
+            let hp = (p.y * size.x + p.x) as u8;
+            *tiles.try_get_mut(p).unwrap() = Tile::BreakableWall { hp };
+        }
+        tiles
+    }
 
-impl AStarBundle {
-    pub fn new(
-        materials: &Res<crate::Materials>,
-        meshes: &mut ResMut<Assets<Mesh>>,
-        path: AStarPath,
-    ) -> Self {
-        let mut mesh = Mesh::new(PrimitiveTopology::LineStrip);
+    /// Tiles on and around the map, some out of bounds on every side
+    fn around(size: IVec2) -> impl Iterator<Item = IVec2> {
+        (-2..size.y + 2).flat_map(move |y| (-2..size.x + 2).map(move |x| IVec2::new(x, y)))
+    }
 
-        let mut verticies = vec![];
+    #[test]
+    fn size_is_width_by_height() {
+        for size in SIZES {
+            let tiles = MapTiles::new_empty(size);
+            assert_eq!(tiles.size(), size);
+            assert_eq!(tiles.num_columns(), size.x as usize);
+            assert_eq!(tiles.num_rows(), size.y as usize);
+            assert_eq!(tiles.positions().count(), (size.x * size.y) as usize);
+            assert!(tiles.positions().all(|p| tiles.in_bounds(p)));
+        }
+    }
 
-        for c in &path.path {
-            verticies.push([c.x as f32, c.y as f32, 0.0]);
+    #[test]
+    fn try_get_agrees_with_in_bounds() {
+        for size in SIZES {
+            let mut tiles = numbered(size);
+            for p in around(size) {
+                let in_bounds = p.cmpge(IVec2::ZERO).all() && p.cmplt(size).all();
+                assert_eq!(tiles.in_bounds(p), in_bounds, "{p} on {size}");
+                assert_eq!(tiles.try_get(p).is_some(), in_bounds, "{p} on {size}");
+                assert_eq!(tiles.try_get_mut(p).is_some(), in_bounds, "{p} on {size}");
+            }
         }
+    }
 
-        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, verticies.clone());
+    #[test]
+    fn try_get_reads_x_then_y() {
+        for size in SIZES {
+            let mut tiles = numbered(size);
+            for p in tiles.positions().collect::<Vec<_>>() {
+                let expected = tiles[(p.y as usize, p.x as usize)];
+                assert_eq!(tiles.try_get(p), Some(&expected));
+                assert_eq!(tiles.try_get_mut(p).copied(), Some(expected));
+            }
+        }
+    }
 
-        Self {
-            path,
-            mesh: MaterialMesh2dBundle {
-                mesh: meshes.add(mesh).into(),
-                transform: Transform::from_xyz(0.0, 0.0, 0.0),
-                material: materials.add(LineMaterial {
-                    color: Color::GREEN,
-                }),
-                ..Default::default()
-            },
+    #[test]
+    fn tile_world_round_trip() {
+        for map_pos in [Vec2::ZERO, Vec2::new(3.0, -11.0), Vec2::new(-7.25, 4.5)] {
+            for size in SIZES {
+                let tiles = numbered(size);
+                for p in tiles.positions() {
+                    let world = MapTiles::tile_to_world(map_pos, p);
+                    assert_eq!(MapTiles::world_to_tile(map_pos, world), p);
+                    assert_eq!(
+                        tiles.tile_at_world(map_pos, world),
+                        tiles.try_get(p).copied()
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn neighbours_stay_in_bounds() {
+        for size in SIZES {
+            let tiles = numbered(size);
+            for p in around(size) {
+                for n in tiles.neighbours(p) {
+                    assert!(tiles.in_bounds(n), "{n} next to {p} on {size}");
+                    assert_eq!((n - p).abs().max_element(), 1);
+                }
+            }
+            // Every tile of a map at least 3x3 has all 8 neighbours in the middle
+            if size.cmpge(IVec2::splat(3)).all() {
+                assert_eq!(tiles.neighbours(IVec2::ONE).count(), 8);
+            }
         }
     }
 }
-*/
@@ -0,0 +1,354 @@
+use array2d::Array2D;
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    prelude::*,
+    utils::BoxedFuture,
+};
+use bevy_rapier2d::prelude::*;
+use std::fmt;
+
+use crate::{MapTiles, Tile};
+
+/// Marks a spawn point in a map file, the tile under it is air
+const SPAWN_CHAR: char = 'S';
+
+impl Tile {
+    /// The character used for this tile in map files
+    pub fn to_char(self) -> char {
+        match self {
+            Tile::Air => '.',
+            Tile::Wall => '#',
+            // Damage isn't saved, breakable walls are always written at full health
+            Tile::BreakableWall { .. } => '+',
+            Tile::Water => '~',
+            Tile::Mud => ',',
+            Tile::Ice => '_',
+            Tile::Barrier => '=',
+        }
+    }
+
+    pub fn from_char(c: char) -> Option<Self> {
+        match c {
+            '.' => Some(Tile::Air),
+            '#' => Some(Tile::Wall),
+            '+' => Some(Tile::BreakableWall {
+                hp: crate::BREAKABLE_WALL_HP,
+            }),
+            '~' => Some(Tile::Water),
+            ',' => Some(Tile::Mud),
+            '_' => Some(Tile::Ice),
+            '=' => Some(Tile::Barrier),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MapParseErrorKind {
+    Empty,
+    UnknownTile(char),
+    /// A row's length differs from the first row's
+    RaggedRow {
+        expected: usize,
+        found: usize,
+    },
+}
+
+/// Why a map file couldn't be parsed. `line` and `column` start at 1
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MapParseError {
+    pub line: usize,
+    pub column: usize,
+    pub kind: MapParseErrorKind,
+}
+
+impl fmt::Display for MapParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}, column {}: ", self.line, self.column)?;
+        match &self.kind {
+            MapParseErrorKind::Empty => write!(f, "map has no rows"),
+            MapParseErrorKind::UnknownTile(c) => write!(f, "unknown tile {c:?}"),
+            MapParseErrorKind::RaggedRow { expected, found } => write!(
+                f,
+                "row is {found} tiles wide but the first row is {expected}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for MapParseError {}
+
+impl MapTiles {
+    /// Parses a map file: one line per row of tiles, `#` for walls, `+` for breakable walls, `.`
+    /// for air, `~` water, `,` mud, `_` ice, `=` barriers and `S` for spawn points. The first line
+    /// is the top of the map. Blank lines and lines starting with `//` are ignored
+    pub fn parse(text: &str) -> Result<Self, MapParseError> {
+        let mut rows: Vec<Vec<Tile>> = vec![];
+        let mut spawns: Vec<(usize, usize)> = vec![];
+        let mut last_line = 0;
+
+        for (line_index, line) in text.lines().enumerate() {
+            let line_no = line_index + 1;
+            last_line = line_no;
+            let line = line.trim_end();
+            if line.is_empty() || line.starts_with("//") {
+                continue;
+            }
+
+            let mut row = vec![];
+            for (column_index, c) in line.chars().enumerate() {
+                let tile = if c == SPAWN_CHAR {
+                    spawns.push((rows.len(), column_index));
+                    Tile::Air
+                } else {
+                    Tile::from_char(c).ok_or(MapParseError {
+                        line: line_no,
+                        column: column_index + 1,
+                        kind: MapParseErrorKind::UnknownTile(c),
+                    })?
+                };
+                row.push(tile);
+            }
+
+            if let Some(first) = rows.first() {
+                if first.len() != row.len() {
+                    return Err(MapParseError {
+                        line: line_no,
+                        column: first.len().min(row.len()) + 1,
+                        kind: MapParseErrorKind::RaggedRow {
+                            expected: first.len(),
+                            found: row.len(),
+                        },
+                    });
+                }
+            }
+            rows.push(row);
+        }
+
+        if rows.is_empty() {
+            return Err(MapParseError {
+                line: last_line.max(1),
+                column: 1,
+                kind: MapParseErrorKind::Empty,
+            });
+        }
+
+        // Text goes top to bottom but tile rows go up with y
+        let height = rows.len();
+        rows.reverse();
+        let tiles = Array2D::from_rows(&rows).expect("rows are all the same length");
+
+        let mut map = MapTiles::from_tiles(tiles);
+        map.spawn_points = spawns
+            .into_iter()
+            .map(|(row, column)| IVec2::new(column as i32, (height - 1 - row) as i32))
+            .collect();
+        Ok(map)
+    }
+
+    /// Writes the map in the format read by `MapTiles::parse`
+    pub fn serialize(&self) -> String {
+        let mut text = String::new();
+        for y in (0..self.num_rows()).rev() {
+            for x in 0..self.num_columns() {
+                let is_spawn = self.spawn_points.contains(&IVec2::new(x as i32, y as i32));
+                text.push(if is_spawn {
+                    SPAWN_CHAR
+                } else {
+                    self[(y, x)].to_char()
+                });
+            }
+            text.push('\n');
+        }
+        text
+    }
+}
+
+#[derive(Default)]
+pub struct MapLoader;
+
+impl AssetLoader for MapLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let text = std::str::from_utf8(bytes)?;
+            let map = MapTiles::parse(text).map_err(|e| {
+                bevy::asset::Error::msg(format!("{}: {e}", load_context.path().display()))
+            })?;
+            load_context.set_default_asset(LoadedAsset::new(map));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["map"]
+    }
+}
+
+/// A map built from a map file, it is (re)built whenever the file loads or changes
+#[derive(Clone, Component, Debug)]
+pub struct MapSource {
+    pub handle: Handle<MapTiles>,
+    pub world_offset: Vec2,
+}
+
+/// The map's file hasn't finished loading yet, gameplay waits until it has
+#[derive(Clone, Component, Debug)]
+pub struct MapLoading;
+
+pub fn spawn_map_from_file(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    path: &str,
+    world_offset: Vec2,
+) -> Entity {
+    commands
+        .spawn((
+            MapSource {
+                handle: asset_server.load(path),
+                world_offset,
+            },
+            MapLoading,
+        ))
+        .id()
+}
+
+fn build_map_sources(
+    mut commands: Commands,
+    maps: Res<Assets<MapTiles>>,
+    asset_server: Res<AssetServer>,
+    q_loading: Query<(Entity, &MapSource), With<MapLoading>>,
+) {
+    for (entity, source) in &q_loading {
+        if let Some(tiles) = maps.get(&source.handle) {
+            commands
+                .entity(entity)
+                .insert(crate::MapBundle::new_from_tiles(
+                    tiles.clone(),
+                    source.world_offset,
+                ))
+                .remove::<MapLoading>();
+        } else if asset_server.get_load_state(&source.handle) == bevy::asset::LoadState::Failed {
+            error!("Failed to load map, spawning an empty one instead");
+            commands
+                .entity(entity)
+                .insert(crate::MapBundle::new_empty(
+                    IVec2::new(20, 10),
+                    source.world_offset,
+                ))
+                .remove::<MapLoading>();
+        }
+    }
+}
+
+/// Replaces the tiles of maps whose file changed. Runs in `Update` because asset events only live
+/// for two frames, and a frame doesn't always run a fixed update
+fn reload_map_sources(
+    mut commands: Commands,
+    mut events: EventReader<AssetEvent<MapTiles>>,
+    maps: Res<Assets<MapTiles>>,
+    q_loaded: Query<(Entity, &MapSource), Without<MapLoading>>,
+) {
+    for event in events.iter() {
+        let AssetEvent::Modified { handle } = event else {
+            continue;
+        };
+        let Some(tiles) = maps.get(handle) else {
+            continue;
+        };
+        for (entity, source) in &q_loaded {
+            if source.handle == *handle {
+                info!("Reloaded map");
+                commands.entity(entity).insert(tiles.clone());
+            }
+        }
+    }
+}
+
+pub fn init_map_file_systems(app: &mut App) {
+    app.add_asset::<MapTiles>().init_asset_loader::<MapLoader>();
+    app.add_systems(
+        FixedUpdate,
+        build_map_sources
+            .before(crate::TankSystemSet::Controllers)
+            .before(PhysicsSet::SyncBackend),
+    );
+    app.add_systems(Update, reload_map_sources);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_error(text: &str) -> MapParseError {
+        MapTiles::parse(text).expect_err("map should not parse")
+    }
+
+    #[test]
+    fn arena_round_trips() {
+        let map = MapTiles::parse(include_str!("../assets/maps/arena.map")).unwrap();
+        assert!(!map.spawn_points.is_empty());
+
+        let again = MapTiles::parse(&map.serialize()).unwrap();
+        assert_eq!(again, map);
+    }
+
+    #[test]
+    fn first_line_is_the_top() {
+        let map = MapTiles::parse("#S\n..\n").unwrap();
+        assert_eq!(map.size(), IVec2::new(2, 2));
+        assert_eq!(map.try_get(IVec2::new(0, 1)), Some(&Tile::Wall));
+        assert_eq!(map.spawn_points, vec![IVec2::new(1, 1)]);
+        assert_eq!(map.serialize(), "#S\n..\n");
+    }
+
+    #[test]
+    fn empty_map() {
+        assert_eq!(
+            parse_error(""),
+            MapParseError {
+                line: 1,
+                column: 1,
+                kind: MapParseErrorKind::Empty,
+            }
+        );
+        assert_eq!(
+            parse_error("// just a comment\n\n"),
+            MapParseError {
+                line: 2,
+                column: 1,
+                kind: MapParseErrorKind::Empty,
+            }
+        );
+    }
+
+    #[test]
+    fn unknown_tile() {
+        assert_eq!(
+            parse_error("// comment\n###\n#x#\n###\n"),
+            MapParseError {
+                line: 3,
+                column: 2,
+                kind: MapParseErrorKind::UnknownTile('x'),
+            }
+        );
+    }
+
+    #[test]
+    fn ragged_row() {
+        assert_eq!(
+            parse_error("###\n\n##\n"),
+            MapParseError {
+                line: 3,
+                column: 3,
+                kind: MapParseErrorKind::RaggedRow {
+                    expected: 3,
+                    found: 2,
+                },
+            }
+        );
+    }
+}
@@ -0,0 +1,778 @@
+use array2d::Array2D;
+use bevy::prelude::*;
+use rand::{seq::SliceRandom, Rng, SeedableRng};
+use rand_chacha::ChaChaRng;
+use smallvec::SmallVec;
+
+use crate::{FlowField, MapTiles, NavGrid, PathOptions, Tile};
+
+/// Longest wall segment `gen_v2` places
+const MAX_WALL_LENGTH: i32 = 6;
+const MAX_PATCH_RADIUS: i32 = 2;
+/// Gives up placing walls after this many tries in a row don't fit
+const WALL_SPAWN_TRIES: usize = 1000;
+
+/// How a generated map repeats itself so no spawn point has an advantage
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Symmetry {
+    None,
+    /// Left half mirrors the right half
+    MirrorX,
+    /// Bottom half mirrors the top half
+    MirrorY,
+    /// Mirrored both ways, 4 identical quarters
+    MirrorXY,
+    /// Turned 180 degrees around the center
+    #[default]
+    Rotational,
+}
+
+impl Symmetry {
+    /// `p` and every tile it maps to on a map of `size`, without duplicates
+    pub fn images(self, p: IVec2, size: IVec2) -> SmallVec<[IVec2; 4]> {
+        let flip_x = IVec2::new(size.x - 1 - p.x, p.y);
+        let flip_y = IVec2::new(p.x, size.y - 1 - p.y);
+        let flip_xy = size - IVec2::ONE - p;
+
+        let mut images: SmallVec<[IVec2; 4]> = match self {
+            Symmetry::None => SmallVec::from_slice(&[p]),
+            Symmetry::MirrorX => SmallVec::from_slice(&[p, flip_x]),
+            Symmetry::MirrorY => SmallVec::from_slice(&[p, flip_y]),
+            Symmetry::MirrorXY => SmallVec::from_slice(&[p, flip_x, flip_y, flip_xy]),
+            Symmetry::Rotational => SmallVec::from_slice(&[p, flip_xy]),
+        };
+        images.sort_by_key(|p| (p.y, p.x));
+        images.dedup();
+        images
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct GenV2Options {
+    /// How much of the map is wall, 0..1
+    pub fullness: f32,
+    /// Number of spawn points to place
+    pub spawn_count: usize,
+    pub symmetry: Symmetry,
+}
+
+impl Default for GenV2Options {
+    fn default() -> Self {
+        Self {
+            fullness: 0.4,
+            spawn_count: 4,
+            symmetry: Symmetry::default(),
+        }
+    }
+}
+
+/// Sets `p` and all its images to `tile`
+fn set_symmetric(tiles: &mut MapTiles, symmetry: Symmetry, p: IVec2, tile: Tile) {
+    let size = tiles.size();
+    for image in symmetry.images(p, size) {
+        if let Some(t) = tiles.try_get_mut(image) {
+            *t = tile;
+        }
+    }
+}
+
+/// Lays out the tiles of a randomly generated map. `generate` then makes sure every tile a tank
+/// can drive on can be reached from every other and places spawn points
+pub trait MapGenerator: Send + Sync + 'static {
+    /// Tiles of a map of `size` with walls all around it. Only `rng` may be used for randomness
+    fn generate_tiles(&self, size: IVec2, rng: &mut ChaChaRng) -> MapTiles;
+
+    /// The symmetry `generate_tiles` follows, kept when connecting regions and placing spawns
+    fn symmetry(&self) -> Symmetry {
+        Symmetry::None
+    }
+
+    /// Generates a map based on `seed` where every tile a tank can drive on can be reached from
+    /// every other, with `spawn_count` spawn points spread out by path distance
+    fn generate(&self, size: IVec2, spawn_count: usize, seed: u64) -> MapTiles {
+        let mut rng = ChaChaRng::seed_from_u64(seed);
+        let mut map = self.generate_tiles(size, &mut rng);
+        let grid = map.connect_regions(self.symmetry(), &PathOptions::default());
+        map.spawn_points = place_spawns(&map, &grid, spawn_count, self.symmetry(), &mut rng);
+        map
+    }
+}
+
+/// Names of the generators `map_generator` knows
+pub const MAP_GENERATORS: [&str; 6] = ["v1", "v2", "cave", "rooms", "maze", "arena"];
+
+/// The generator called `name` with its default settings, see `MAP_GENERATORS`
+pub fn map_generator(name: &str) -> Option<Box<dyn MapGenerator>> {
+    Some(match name {
+        "v1" => Box::new(GenV1 { fullness: 0.5 }),
+        "v2" => Box::new(GenV2Options::default()),
+        "cave" => Box::new(CaveGenerator::default()),
+        "rooms" => Box::new(RoomsGenerator::default()),
+        "maze" => Box::new(MazeGenerator::default()),
+        "arena" => Box::new(ArenaGenerator::default()),
+        _ => return None,
+    })
+}
+
+/// Scattered wall segments and terrain patches
+impl MapGenerator for GenV2Options {
+    fn generate_tiles(&self, size: IVec2, rng: &mut ChaChaRng) -> MapTiles {
+        let mut map = MapTiles::new_empty(size);
+        let symmetry = self.symmetry;
+
+        let wanted_filled_tiles = (size.x * size.y) as f32 * self.fullness / 2.0;
+        let mut filled = 0;
+        let mut tries = 0;
+        while (filled as f32) < wanted_filled_tiles && tries < WALL_SPAWN_TRIES {
+            tries += 1;
+            let len = rng.gen_range(1..=MAX_WALL_LENGTH);
+            let dir = *[IVec2::X, IVec2::NEG_X, IVec2::Y, IVec2::NEG_Y]
+                .choose(rng)
+                .unwrap();
+            let pos = IVec2::new(rng.gen_range(0..size.x), rng.gen_range(0..size.y));
+
+            let segment: Vec<IVec2> = (0..len).map(|v| pos + dir * v).collect();
+            let fits = segment.iter().all(|p| {
+                symmetry
+                    .images(*p, size)
+                    .iter()
+                    .all(|image| map.try_get(*image) == Some(&Tile::Air))
+            });
+            if !fits {
+                continue;
+            }
+
+            for p in segment {
+                filled += symmetry.images(p, size).len();
+                set_symmetric(&mut map, symmetry, p, Tile::Wall);
+            }
+            tries = 0;
+        }
+
+        // Round patches of terrain over the open floor, like `gen_v1`
+        let patches = (size.x * size.y) as usize / 60;
+        for _ in 0..patches {
+            let terrain = *[Tile::Water, Tile::Mud, Tile::Ice].choose(rng).unwrap();
+            let radius = rng.gen_range(1..=MAX_PATCH_RADIUS);
+            let center = IVec2::new(rng.gen_range(0..size.x), rng.gen_range(0..size.y));
+
+            for y in center.y - radius..=center.y + radius {
+                for x in center.x - radius..=center.x + radius {
+                    let p = IVec2::new(x, y);
+                    if (p - center).length_squared() > radius * radius {
+                        continue;
+                    }
+                    if map.try_get(p) == Some(&Tile::Air) {
+                        set_symmetric(&mut map, symmetry, p, terrain);
+                    }
+                }
+            }
+        }
+
+        map
+    }
+
+    fn symmetry(&self) -> Symmetry {
+        self.symmetry
+    }
+}
+
+impl MapTiles {
+    /// Randomly generates a map based on `seed`, see `MapGenerator::generate`. With a `Symmetry`
+    /// the walls, terrain and spawn points all follow it, so every spawn point sees the same map
+    pub fn gen_v2(size: IVec2, options: &GenV2Options, seed: u64) -> MapTiles {
+        options.generate(size, options.spawn_count, seed)
+    }
+
+    /// Carves corridors until every tile a tank fits on can be reached from every other, checked
+    /// with a `FlowField`. Tiles no corridor can be carved to are filled with wall instead.
+    /// Returns the final `NavGrid`. `options` should match the `NavGrid`s the tanks drive with,
+    /// see `update_map_nav`
+    fn connect_regions(&mut self, symmetry: Symmetry, options: &PathOptions) -> NavGrid {
+        let size = self.size();
+        let center = size / 2;
+
+        loop {
+            let grid = NavGrid::new(self, options);
+            let open: Vec<IVec2> = self
+                .positions()
+                .filter(|p| grid.cost(*p).is_some())
+                .collect();
+            let Some(root) = open
+                .iter()
+                .copied()
+                .min_by_key(|p| (*p - center).length_squared())
+            else {
+                // Nothing to connect
+                return grid;
+            };
+
+            let field = FlowField::new(&grid, root);
+            let (reached, unreached): (Vec<IVec2>, Vec<IVec2>) =
+                open.into_iter().partition(|p| field.distance(*p).is_some());
+            let Some(&from) = unreached.first() else {
+                return grid;
+            };
+
+            // Straight to the closest tile we can already reach, turning once. Two tiles wide, or
+            // the tank wouldn't fit through it, widened away from the border
+            let to = reached
+                .iter()
+                .copied()
+                .min_by_key(|p| {
+                    let d = (*p - from).abs();
+                    d.x + d.y
+                })
+                .unwrap_or(root);
+            let corner = IVec2::new(to.x, from.y);
+            let inwards = |v: i32, len: i32| if v + 1 < len - 1 { 1 } else { -1 };
+            let row_offset = IVec2::new(0, inwards(from.y, size.y));
+            let column_offset = IVec2::new(inwards(to.x, size.x), 0);
+
+            let mut carved = false;
+            let mut carve = |p: IVec2| {
+                if !on_border(p, size) && self.try_get(p).is_some_and(|t| t.blocks_tanks()) {
+                    set_symmetric(self, symmetry, p, Tile::Air);
+                    carved = true;
+                }
+            };
+            for x in from.x.min(corner.x)..=from.x.max(corner.x) {
+                carve(IVec2::new(x, from.y));
+                carve(IVec2::new(x, from.y) + row_offset);
+            }
+            for y in corner.y.min(to.y)..=corner.y.max(to.y) {
+                carve(IVec2::new(to.x, y));
+                carve(IVec2::new(to.x, y) + column_offset);
+            }
+            carve(corner + row_offset + column_offset);
+            // Too small to fit a corridor, wall in the tiles left over so tanks can't spawn there
+            if !carved {
+                for p in unreached {
+                    if let Some(tile) = self.try_get_mut(p) {
+                        *tile = Tile::Wall;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Picks spawn points far apart from each other by path distance, preferring open tiles with
+/// nothing around them. Spawn points come in groups of images under `symmetry`
+fn place_spawns(
+    tiles: &MapTiles,
+    grid: &NavGrid,
+    spawn_count: usize,
+    symmetry: Symmetry,
+    rng: &mut ChaChaRng,
+) -> Vec<IVec2> {
+    let size = tiles.size();
+    let is_open = |p: IVec2| tiles.try_get(p) == Some(&Tile::Air) && grid.cost(p).is_some();
+
+    let mut candidates: Vec<IVec2> = tiles
+        .positions()
+        .filter(|p| is_open(*p) && tiles.neighbours(*p).all(is_open))
+        .collect();
+    if candidates.len() < spawn_count {
+        candidates = tiles
+            .positions()
+            .filter(|p| grid.cost(*p).is_some())
+            .collect();
+    }
+    if candidates.is_empty() {
+        return vec![];
+    }
+
+    let mut spawns: Vec<IVec2> = vec![];
+    let mut fields: Vec<FlowField> = vec![];
+    while spawns.len() < spawn_count {
+        let pick = if spawns.is_empty() {
+            *candidates.choose(rng).unwrap()
+        } else {
+            // The candidate furthest from its closest spawn point
+            let Some(pick) = candidates
+                .iter()
+                .copied()
+                .filter(|p| !spawns.contains(p))
+                .max_by_key(|p| {
+                    fields
+                        .iter()
+                        .map(|field| field.distance(*p).unwrap_or(0))
+                        .min()
+                        .unwrap_or(0)
+                })
+            else {
+                break;
+            };
+            pick
+        };
+
+        for image in symmetry.images(pick, size) {
+            if spawns.len() < spawn_count && !spawns.contains(&image) {
+                fields.push(FlowField::new(grid, image));
+                spawns.push(image);
+            }
+        }
+    }
+    spawns
+}
+
+fn on_border(p: IVec2, size: IVec2) -> bool {
+    p.x == 0 || p.y == 0 || p.x == size.x - 1 || p.y == size.y - 1
+}
+
+/// A map of `size` that is wall everywhere
+fn solid_map(size: IVec2) -> MapTiles {
+    MapTiles::from_tiles(Array2D::filled_with(
+        Tile::Wall,
+        size.y as usize,
+        size.x as usize,
+    ))
+}
+
+/// Sets the tiles from `min` up to `max` to `Tile::Air`, leaving the border walls alone
+fn carve_rect(tiles: &mut MapTiles, min: IVec2, max: IVec2) {
+    let size = tiles.size();
+    for y in min.y..max.y {
+        for x in min.x..max.x {
+            let p = IVec2::new(x, y);
+            if on_border(p, size) {
+                continue;
+            }
+            if let Some(t) = tiles.try_get_mut(p) {
+                *t = Tile::Air;
+            }
+        }
+    }
+}
+
+/// `MapTiles::gen_v1`, with its regions connected and spawn points added
+#[derive(Clone, Debug)]
+pub struct GenV1 {
+    /// See `MapTiles::gen_v1`
+    pub fullness: f32,
+}
+
+impl MapGenerator for GenV1 {
+    fn generate_tiles(&self, size: IVec2, rng: &mut ChaChaRng) -> MapTiles {
+        MapTiles::gen_v1(size, self.fullness, rng.gen())
+    }
+}
+
+/// Cellular automaton caves, random noise smoothed until the walls clump together
+#[derive(Clone, Debug)]
+pub struct CaveGenerator {
+    /// Chance of each tile starting out as wall, 0..1
+    pub fill: f32,
+    /// Smoothing passes, more passes give rounder caves
+    pub steps: usize,
+}
+
+impl Default for CaveGenerator {
+    fn default() -> Self {
+        Self {
+            fill: 0.45,
+            steps: 4,
+        }
+    }
+}
+
+impl MapGenerator for CaveGenerator {
+    fn generate_tiles(&self, size: IVec2, rng: &mut ChaChaRng) -> MapTiles {
+        let mut map = MapTiles::new_empty(size);
+        for p in map.positions() {
+            if !on_border(p, size) && rng.gen_bool(self.fill.clamp(0.0, 1.0) as f64) {
+                map[(p.y as usize, p.x as usize)] = Tile::Wall;
+            }
+        }
+
+        for _ in 0..self.steps {
+            let previous = map.clone();
+            for p in previous.positions() {
+                if on_border(p, size) {
+                    continue;
+                }
+                // Off the map counts as wall
+                let walls = crate::NEIGHBOURS
+                    .iter()
+                    .filter(|step| {
+                        previous
+                            .try_get(p + **step)
+                            .is_none_or(|t| *t == Tile::Wall)
+                    })
+                    .count();
+                if walls >= 5 {
+                    map[(p.y as usize, p.x as usize)] = Tile::Wall;
+                } else if walls <= 3 {
+                    map[(p.y as usize, p.x as usize)] = Tile::Air;
+                }
+            }
+        }
+        map
+    }
+}
+
+/// Rectangular rooms joined by corridors, laid out by splitting the map in two over and over
+#[derive(Clone, Debug)]
+pub struct RoomsGenerator {
+    /// Rooms are at least this big, including the wall on one side
+    pub min_room: i32,
+    /// Areas wider or taller than this are split again
+    pub max_area: i32,
+    pub corridor_width: i32,
+}
+
+impl Default for RoomsGenerator {
+    fn default() -> Self {
+        Self {
+            min_room: 5,
+            max_area: 12,
+            corridor_width: 2,
+        }
+    }
+}
+
+impl RoomsGenerator {
+    /// Carves rooms into the area from `min` up to `max` and joins them, returning the center of
+    /// one of them
+    fn split(&self, map: &mut MapTiles, min: IVec2, max: IVec2, rng: &mut ChaChaRng) -> IVec2 {
+        let extent = max - min;
+        let axis = if extent.x >= extent.y { 0 } else { 1 };
+        let can_split = extent[axis] >= 2 * self.min_room;
+
+        if extent.max_element() > self.max_area && can_split {
+            let cut = min[axis] + rng.gen_range(self.min_room..=extent[axis] - self.min_room);
+            let mut first_max = max;
+            first_max[axis] = cut;
+            let mut second_min = min;
+            second_min[axis] = cut;
+
+            let a = self.split(map, min, first_max, rng);
+            let b = self.split(map, second_min, max, rng);
+            self.corridor(map, a, b);
+            return if rng.gen_bool(0.5) { a } else { b };
+        }
+
+        // Leave a wall on the far side of the room so neighbouring rooms don't merge
+        let max_size = (extent - IVec2::ONE).max(IVec2::ONE);
+        let min_size = IVec2::splat(self.min_room - 1).clamp(IVec2::ONE, max_size);
+        let room_size = IVec2::new(
+            rng.gen_range(min_size.x..=max_size.x),
+            rng.gen_range(min_size.y..=max_size.y),
+        );
+        let room_min = min
+            + IVec2::new(
+                rng.gen_range(0..=max_size.x - room_size.x),
+                rng.gen_range(0..=max_size.y - room_size.y),
+            );
+        carve_rect(map, room_min, room_min + room_size);
+        room_min + room_size / 2
+    }
+
+    /// Carves a corridor from `a` to `b`, turning once
+    fn corridor(&self, map: &mut MapTiles, a: IVec2, b: IVec2) {
+        let width = IVec2::splat(self.corridor_width.max(1));
+        let corner = IVec2::new(b.x, a.y);
+        carve_rect(map, a.min(corner), a.max(corner) + width);
+        carve_rect(map, corner.min(b), corner.max(b) + width);
+    }
+}
+
+impl MapGenerator for RoomsGenerator {
+    fn generate_tiles(&self, size: IVec2, rng: &mut ChaChaRng) -> MapTiles {
+        let mut map = solid_map(size);
+        self.split(&mut map, IVec2::ONE, size - IVec2::ONE, rng);
+        map
+    }
+}
+
+/// A maze dug by a recursive backtracker, with a few extra openings so there is more than one
+/// way around
+#[derive(Clone, Debug)]
+pub struct MazeGenerator {
+    /// Width of the passages, the walls between them are one tile thick
+    pub passage_width: i32,
+    /// Chance of opening each wall the backtracker left between two cells, 0..1
+    pub loops: f32,
+}
+
+impl Default for MazeGenerator {
+    fn default() -> Self {
+        Self {
+            passage_width: 2,
+            loops: 0.1,
+        }
+    }
+}
+
+impl MapGenerator for MazeGenerator {
+    fn generate_tiles(&self, size: IVec2, rng: &mut ChaChaRng) -> MapTiles {
+        let width = self.passage_width.max(1);
+        let pitch = width + 1;
+        let cells = (size - IVec2::ONE) / pitch;
+        if cells.x < 1 || cells.y < 1 {
+            return MapTiles::new_empty(size);
+        }
+
+        let mut map = solid_map(size);
+        let origin = |cell: IVec2| IVec2::ONE + cell * pitch;
+        // Carves both cells and the wall between them
+        let open = |map: &mut MapTiles, a: IVec2, b: IVec2| {
+            carve_rect(map, origin(a.min(b)), origin(a.max(b)) + width);
+        };
+        let in_maze = |cell: IVec2| cell.cmpge(IVec2::ZERO).all() && cell.cmplt(cells).all();
+        let directions = [IVec2::X, IVec2::NEG_X, IVec2::Y, IVec2::NEG_Y];
+
+        let mut visited = vec![false; (cells.x * cells.y) as usize];
+        let index = |cell: IVec2| (cell.y * cells.x + cell.x) as usize;
+
+        let start = IVec2::new(rng.gen_range(0..cells.x), rng.gen_range(0..cells.y));
+        visited[index(start)] = true;
+        open(&mut map, start, start);
+        let mut stack = vec![start];
+        while let Some(&cell) = stack.last() {
+            let unvisited: SmallVec<[IVec2; 4]> = directions
+                .iter()
+                .map(|dir| cell + *dir)
+                .filter(|next| in_maze(*next) && !visited[index(*next)])
+                .collect();
+            let Some(&next) = unvisited.choose(rng) else {
+                stack.pop();
+                continue;
+            };
+            visited[index(next)] = true;
+            open(&mut map, cell, next);
+            stack.push(next);
+        }
+
+        for y in 0..cells.y {
+            for x in 0..cells.x {
+                let cell = IVec2::new(x, y);
+                for dir in [IVec2::X, IVec2::Y] {
+                    let next = cell + dir;
+                    if in_maze(next) && rng.gen_bool(self.loops.clamp(0.0, 1.0) as f64) {
+                        open(&mut map, cell, next);
+                    }
+                }
+            }
+        }
+        map
+    }
+}
+
+/// Open floor with a grid of pillars to take cover behind, some of them breakable
+#[derive(Clone, Debug)]
+pub struct ArenaGenerator {
+    /// Distance between the corners of neighbouring pillars
+    pub spacing: i32,
+    pub pillar_size: i32,
+    /// Chance of each pillar being a `Tile::BreakableWall`, 0..1
+    pub breakable: f32,
+}
+
+impl Default for ArenaGenerator {
+    fn default() -> Self {
+        Self {
+            spacing: 5,
+            pillar_size: 2,
+            breakable: 0.3,
+        }
+    }
+}
+
+impl MapGenerator for ArenaGenerator {
+    fn generate_tiles(&self, size: IVec2, rng: &mut ChaChaRng) -> MapTiles {
+        // Room to drive between the outer pillars and the walls
+        const MARGIN: i32 = 2;
+
+        let mut map = MapTiles::new_empty(size);
+        let spacing = self.spacing.max(self.pillar_size + 1);
+        let room = size - IVec2::splat(2 * MARGIN + self.pillar_size);
+        if room.cmplt(IVec2::ZERO).any() {
+            return map;
+        }
+
+        // Centered, so the pillars are as far from every wall
+        let count = room / spacing + IVec2::ONE;
+        let span = (count - IVec2::ONE) * spacing + IVec2::splat(self.pillar_size);
+        let start = (size - span) / 2;
+
+        for y in 0..count.y {
+            for x in 0..count.x {
+                // Roll once for the pillar and every pillar it maps to, which set_symmetric covers
+                let pillar = IVec2::new(x, y);
+                if self.symmetry().images(pillar, count)[0] != pillar {
+                    continue;
+                }
+                let tile = if rng.gen_bool(self.breakable.clamp(0.0, 1.0) as f64) {
+                    Tile::BreakableWall {
+                        hp: crate::BREAKABLE_WALL_HP,
+                    }
+                } else {
+                    Tile::Wall
+                };
+
+                let min = start + pillar * spacing;
+                for dy in 0..self.pillar_size {
+                    for dx in 0..self.pillar_size {
+                        let p = min + IVec2::new(dx, dy);
+                        set_symmetric(&mut map, self.symmetry(), p, tile);
+                    }
+                }
+            }
+        }
+        map
+    }
+
+    fn symmetry(&self) -> Symmetry {
+        Symmetry::Rotational
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZES: [IVec2; 4] = [
+        IVec2::new(20, 10),
+        IVec2::new(10, 20),
+        IVec2::new(33, 17),
+        IVec2::new(16, 25),
+    ];
+    const SEEDS: [u64; 4] = [0, 1, 0x754620, u64::MAX];
+
+    /// Runs `check` on a map from every generator for every size and seed
+    fn for_each_map(check: impl Fn(&str, IVec2, u64, &MapTiles)) {
+        for name in MAP_GENERATORS {
+            let generator = map_generator(name).unwrap();
+            for size in SIZES {
+                for seed in SEEDS {
+                    let tiles = generator.generate(size, 4, seed);
+                    check(name, size, seed, &tiles);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn every_name_has_a_generator() {
+        for name in MAP_GENERATORS {
+            assert!(map_generator(name).is_some(), "{name}");
+        }
+        assert!(map_generator("nope").is_none());
+    }
+
+    #[test]
+    fn maps_have_the_requested_size() {
+        for_each_map(|name, size, seed, tiles| {
+            assert_eq!(tiles.size(), size, "{name} seed {seed}");
+        });
+    }
+
+    #[test]
+    fn maps_are_walled_in() {
+        for_each_map(|name, size, seed, tiles| {
+            for p in tiles.positions().filter(|p| on_border(*p, size)) {
+                let tile = tiles.try_get(p).unwrap();
+                assert!(
+                    tile.blocks_tanks() && tile.blocks_bullets(),
+                    "{name} {size} seed {seed}: {tile:?} at {p}"
+                );
+            }
+        });
+    }
+
+    #[test]
+    fn every_drivable_tile_is_reachable() {
+        for_each_map(|name, size, seed, tiles| {
+            let grid = NavGrid::new(tiles, &PathOptions::default());
+            let drivable: Vec<IVec2> = tiles
+                .positions()
+                .filter(|p| grid.cost(*p).is_some())
+                .collect();
+            assert!(!drivable.is_empty(), "{name} {size} seed {seed}");
+
+            let field = FlowField::new(&grid, drivable[0]);
+            for p in &drivable {
+                assert!(
+                    field.distance(*p).is_some(),
+                    "{name} {size} seed {seed}: {p} can't be reached"
+                );
+            }
+        });
+    }
+
+    #[test]
+    fn unconnectable_tiles_are_walled_in() {
+        // Mud doesn't block tanks, so no corridor can be carved through it
+        let options = PathOptions {
+            tile_cost: |tile| (tile != Tile::Mud).then_some(1),
+            ..Default::default()
+        };
+        let mut tiles = MapTiles::parse(
+            "##########\n\
+             #....,,..#\n\
+             #....,,..#\n\
+             #....,,..#\n\
+             #....,,..#\n\
+             ##########",
+        )
+        .unwrap();
+
+        let grid = tiles.connect_regions(Symmetry::None, &options);
+        for y in 1..5 {
+            assert!(grid.cost(IVec2::new(2, y)).is_some());
+            for x in 7..9 {
+                assert_eq!(tiles.try_get(IVec2::new(x, y)), Some(&Tile::Wall));
+                assert!(grid.cost(IVec2::new(x, y)).is_none());
+            }
+        }
+    }
+
+    #[test]
+    fn spawn_points_are_drivable() {
+        for_each_map(|name, size, seed, tiles| {
+            let grid = NavGrid::new(tiles, &PathOptions::default());
+            assert!(!tiles.spawn_points.is_empty(), "{name} {size} seed {seed}");
+            assert!(tiles.spawn_points.len() <= 4);
+            for p in &tiles.spawn_points {
+                assert!(grid.cost(*p).is_some(), "{name} {size} seed {seed}: {p}");
+            }
+        });
+    }
+
+    #[test]
+    fn arena_pillars_are_all_one_tile() {
+        let arena = ArenaGenerator {
+            breakable: 0.5,
+            ..Default::default()
+        };
+        for size in SIZES {
+            for seed in SEEDS {
+                let tiles = arena.generate_tiles(size, &mut ChaChaRng::seed_from_u64(seed));
+                // Pillars and their mirror images are the only walls off the border
+                let pillar = |p: IVec2| {
+                    let tile = *tiles.try_get(p)?;
+                    (tile != Tile::Air && !on_border(p, size)).then_some(tile)
+                };
+                for p in tiles.positions() {
+                    let Some(tile) = pillar(p) else { continue };
+                    for next in [p + IVec2::X, p + IVec2::Y] {
+                        assert!(
+                            pillar(next).is_none_or(|t| t == tile),
+                            "{size} seed {seed}: {p} and {next}"
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn same_seed_same_map() {
+        for_each_map(|name, size, seed, tiles| {
+            let again = map_generator(name).unwrap().generate(size, 4, seed);
+            assert!(*tiles == again, "{name} {size} seed {seed}");
+        });
+    }
+}
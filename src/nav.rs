@@ -0,0 +1,358 @@
+use array2d::Array2D;
+use bevy::prelude::*;
+
+use crate::{AStarPath, MapTiles, Tile};
+
+/// Path cost of moving one tile straight on tiles with a `tile_cost` of 1
+const STRAIGHT_COST: usize = 100;
+/// Path cost of moving one tile diagonally, `STRAIGHT_COST * sqrt(2)`
+const DIAGONAL_COST: usize = 141;
+/// Spacing of the points checked along a straight line when smoothing paths
+const SMOOTHING_STEP: f32 = 0.1;
+/// Where on a tile a tank may stand, relative to the tile's center: the center, then the middle
+/// of each edge, then each corner
+const TILE_ANCHORS: [Vec2; 9] = [
+    Vec2::new(0.0, 0.0),
+    Vec2::new(0.5, 0.0),
+    Vec2::new(-0.5, 0.0),
+    Vec2::new(0.0, 0.5),
+    Vec2::new(0.0, -0.5),
+    Vec2::new(0.5, 0.5),
+    Vec2::new(0.5, -0.5),
+    Vec2::new(-0.5, 0.5),
+    Vec2::new(-0.5, -0.5),
+];
+
+pub const NEIGHBOURS: [IVec2; 8] = [
+    IVec2::new(1, 0),
+    IVec2::new(-1, 0),
+    IVec2::new(0, 1),
+    IVec2::new(0, -1),
+    IVec2::new(1, 1),
+    IVec2::new(1, -1),
+    IVec2::new(-1, 1),
+    IVec2::new(-1, -1),
+];
+
+#[derive(Clone, Debug)]
+pub struct PathOptions {
+    /// How close the center of the tank may get to a tile it collides with. Half the width of
+    /// the tank by default, so it fits through corridors as wide as its hull
+    pub radius: f32,
+    /// Multiplier for the cost of driving over a tile, `None` for tiles that can't be entered
+    pub tile_cost: fn(Tile) -> Option<usize>,
+    /// Skip waypoints the tank can reach by driving in a straight line
+    pub smooth: bool,
+}
+
+impl Default for PathOptions {
+    fn default() -> Self {
+        Self {
+            radius: crate::TANK_SIZE.max_element() / 2.0,
+            tile_cost: Tile::path_cost,
+            smooth: true,
+        }
+    }
+}
+
+/// A path in world space
+#[derive(Clone, Debug)]
+pub struct NavPath {
+    /// Points to drive to in order, not including the start. The last is the goal
+    pub waypoints: Vec<Vec2>,
+    /// Cost of the grid path, see `NavGrid::astar`
+    pub cost: usize,
+}
+
+/// Which tiles of a map a tank of one size fits on and what each costs to drive over. Build it
+/// once per map and `PathOptions` to search many paths, and again when the tiles change
+#[derive(Clone, Debug)]
+pub struct NavGrid {
+    /// Cost of entering each tile, `None` where the tank doesn't fit
+    costs: Array2D<Option<usize>>,
+    /// Tiles the tank collides with
+    blocked: Array2D<bool>,
+    radius: f32,
+    smooth: bool,
+}
+
+/// Distance from `point` to the nearest point of the square tile at `tile`
+fn tile_distance(point: Vec2, tile: IVec2) -> f32 {
+    let offset = (point - (tile.as_vec2() + 0.5)).abs() - 0.5;
+    offset.max(Vec2::ZERO).length()
+}
+
+impl NavGrid {
+    pub fn new(tiles: &MapTiles, options: &PathOptions) -> Self {
+        let rows = tiles.num_rows();
+        let columns = tiles.num_columns();
+
+        let mut blocked = Array2D::filled_with(false, rows, columns);
+        for ((y, x), tile) in tiles.enumerate_row_major() {
+            blocked[(y, x)] = tile.blocks_tanks() || (options.tile_cost)(*tile).is_none();
+        }
+
+        let mut grid = Self {
+            costs: Array2D::filled_with(None, rows, columns),
+            blocked,
+            radius: options.radius,
+            smooth: options.smooth,
+        };
+        for ((y, x), tile) in tiles.enumerate_row_major() {
+            let p = IVec2::new(x as i32, y as i32);
+            if grid.anchor(p).is_some() {
+                grid.costs[(y, x)] = (options.tile_cost)(*tile);
+            }
+        }
+        grid
+    }
+
+    /// The point closest to the center of `tile`, in map local coordinates, where the tank fits.
+    /// A tank wider than a tile only fits in a two tile wide corridor on the edge between them
+    fn anchor(&self, tile: IVec2) -> Option<Vec2> {
+        if self.is_blocked(tile) {
+            return None;
+        }
+        let center = tile.as_vec2() + 0.5;
+        TILE_ANCHORS
+            .iter()
+            .map(|offset| center + *offset)
+            .find(|point| self.fits(*point))
+    }
+
+    /// Where a path through `tile` passes, see `anchor`
+    fn waypoint(&self, tile: IVec2) -> Vec2 {
+        self.anchor(tile).unwrap_or(tile.as_vec2() + 0.5)
+    }
+
+    fn is_blocked(&self, tile: IVec2) -> bool {
+        if tile.x < 0 || tile.y < 0 {
+            return true;
+        }
+        self.blocked
+            .get(tile.y as usize, tile.x as usize)
+            .copied()
+            .unwrap_or(true)
+    }
+
+    /// Cost of entering `tile`, `None` when the tank doesn't fit there or it is off the map
+    pub fn cost(&self, tile: IVec2) -> Option<usize> {
+        if tile.x < 0 || tile.y < 0 {
+            return None;
+        }
+        self.costs.get(tile.y as usize, tile.x as usize).copied()?
+    }
+
+    /// Whether a tank centered at `point`, in map local coordinates, is clear of every tile it
+    /// collides with
+    fn fits(&self, point: Vec2) -> bool {
+        // `point` can be on the edge of a tile, so look one tile further
+        let reach = self.radius.ceil() as i32 + 1;
+        let tile = point.floor().as_ivec2();
+        for dy in -reach..=reach {
+            for dx in -reach..=reach {
+                let other = tile + IVec2::new(dx, dy);
+                if self.is_blocked(other) && tile_distance(point, other) < self.radius {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    /// Cost of moving from `from` one tile in direction `step`, one of `NEIGHBOURS`. Moving
+    /// diagonally is only allowed when both tiles beside the move can be entered too
+    pub fn step_cost(&self, from: IVec2, step: IVec2) -> Option<usize> {
+        let cost = self.cost(from + step)?;
+        let diagonal = step.x != 0 && step.y != 0;
+        if !diagonal {
+            return Some(STRAIGHT_COST * cost);
+        }
+        self.cost(from + IVec2::new(step.x, 0))?;
+        self.cost(from + IVec2::new(0, step.y))?;
+        Some(DIAGONAL_COST * cost)
+    }
+
+    /// Width and height in tiles
+    pub fn size(&self) -> IVec2 {
+        IVec2::new(
+            self.costs.num_columns() as i32,
+            self.costs.num_rows() as i32,
+        )
+    }
+
+    /// The tiles that became cheaper or could be entered in `new`, or `None` if any tile got
+    /// more expensive, closed off, or the size changed
+    pub fn opened_tiles(&self, new: &NavGrid) -> Option<Vec<IVec2>> {
+        if self.size() != new.size() {
+            return None;
+        }
+        let mut opened = vec![];
+        for ((y, x), old) in self.costs.enumerate_row_major() {
+            let tile = IVec2::new(x as i32, y as i32);
+            match (*old, new.costs[(y, x)]) {
+                (Some(_), None) => return None,
+                (Some(old), Some(new)) if new > old => return None,
+                (Some(old), Some(new)) if new == old => {}
+                (None, None) => {}
+                _ => opened.push(tile),
+            }
+        }
+        Some(opened)
+    }
+
+    /// 8-connected A* from `start` to `goal`. Moving diagonally is only allowed when both tiles
+    /// beside the move can be entered too, so paths never cut corners
+    pub fn astar(&self, start: IVec2, goal: IVec2) -> Option<AStarPath> {
+        self.cost(start)?;
+        self.cost(goal)?;
+
+        let successors = |c: &IVec2| {
+            let mut v = smallvec::SmallVec::<[(IVec2, usize); 8]>::new();
+            for step in NEIGHBOURS {
+                if let Some(cost) = self.step_cost(*c, step) {
+                    v.push((*c + step, cost));
+                }
+            }
+            v.into_iter()
+        };
+        // Octile distance, admissible as no tile costs less than 1
+        let heuristic = |c: &IVec2| {
+            let d = (*c - goal).abs();
+            let (long, short) = (d.max_element() as usize, d.min_element() as usize);
+            STRAIGHT_COST * (long - short) + DIAGONAL_COST * short
+        };
+        pathfinding::directed::astar::astar(&start, successors, heuristic, |c| *c == goal)
+            .map(|(path, cost)| AStarPath { path, cost })
+    }
+
+    /// The tile at `point` if a tank fits somewhere on it, otherwise the closest neighbouring
+    /// tile it fits on. Lets paths start from a tank that is pressed against a wall
+    fn nearest_open_tile(&self, point: Vec2) -> Option<IVec2> {
+        let tile = point.floor().as_ivec2();
+        if self.cost(tile).is_some() {
+            return Some(tile);
+        }
+        NEIGHBOURS
+            .iter()
+            .map(|step| tile + *step)
+            .filter(|t| self.cost(*t).is_some())
+            .min_by(|a, b| {
+                let da = tile_distance(point, *a);
+                let db = tile_distance(point, *b);
+                da.total_cmp(&db)
+            })
+    }
+
+    /// Whether a tank can drive straight from `a` to `b` without hitting anything or crossing
+    /// tiles costing more than `max_cost`
+    fn straight_line_clear(&self, a: Vec2, b: Vec2, max_cost: usize) -> bool {
+        let steps = ((b - a).length() / SMOOTHING_STEP).ceil().max(1.0) as usize;
+        (0..=steps).all(|i| {
+            let point = a.lerp(b, i as f32 / steps as f32);
+            let cost = self.cost(point.floor().as_ivec2());
+            cost.is_some_and(|cost| cost <= max_cost) && self.fits(point)
+        })
+    }
+
+    /// Removes every point that can be skipped by driving straight to a later one (string
+    /// pulling), without taking shortcuts over more expensive ground than the original path
+    fn smooth_path(&self, points: &[Vec2]) -> Vec<Vec2> {
+        let mut smoothed = vec![points[0]];
+        let mut from = 0;
+        while from < points.len() - 1 {
+            let mut to = from + 1;
+            for candidate in (from + 2..points.len()).rev() {
+                let max_cost = points[from..=candidate]
+                    .iter()
+                    .filter_map(|p| self.cost(p.floor().as_ivec2()))
+                    .max()
+                    .unwrap_or(1);
+                if self.straight_line_clear(points[from], points[candidate], max_cost) {
+                    to = candidate;
+                    break;
+                }
+            }
+            smoothed.push(points[to]);
+            from = to;
+        }
+        smoothed
+    }
+
+    /// Finds a path between two world positions on a map whose origin is at `map_pos`
+    pub fn find_path(&self, map_pos: Vec2, from: Vec2, to: Vec2) -> Option<NavPath> {
+        let (from, to) = (from - map_pos, to - map_pos);
+        let start = self.nearest_open_tile(from)?;
+        let goal = self.nearest_open_tile(to)?;
+        let path = self.astar(start, goal)?;
+
+        // Drive to the exact goal if the tank fits there, otherwise where it fits on its tile
+        let end = if goal == to.floor().as_ivec2() && self.fits(to) {
+            to
+        } else {
+            self.waypoint(goal)
+        };
+        let mut points = vec![from];
+        points.extend(path.path.iter().skip(1).map(|t| self.waypoint(*t)));
+        if path.path.len() > 1 {
+            points.pop();
+        }
+        points.push(end);
+
+        let points = if self.smooth {
+            self.smooth_path(&points)
+        } else {
+            points
+        };
+
+        Some(NavPath {
+            waypoints: points.into_iter().skip(1).map(|p| p + map_pos).collect(),
+            cost: path.cost,
+        })
+    }
+}
+
+impl MapTiles {
+    /// Finds a path between two world positions on this map placed with its origin at
+    /// `map_pos`, see `NavGrid::find_path`. Build a `NavGrid` once instead when searching the
+    /// same map many times
+    pub fn find_path(
+        &self,
+        map_pos: Vec2,
+        from: Vec2,
+        to: Vec2,
+        options: &PathOptions,
+    ) -> Option<NavPath> {
+        NavGrid::new(self, options).find_path(map_pos, from, to)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Two rooms joined by a corridor one tile wide
+    const CORRIDOR: &str = "\
+###########
+#...###...#
+#.........#
+#...###...#
+###########
+";
+
+    #[test]
+    fn tank_fits_through_one_tile_corridor() {
+        let tiles = MapTiles::parse(CORRIDOR).unwrap();
+        let (from, to) = (Vec2::new(1.5, 2.5), Vec2::new(9.5, 2.5));
+
+        let path = tiles.find_path(Vec2::ZERO, from, to, &PathOptions::default());
+        assert_eq!(path.unwrap().waypoints.last(), Some(&to));
+
+        // Clearance for turning on the spot is too wide for the corridor
+        let wide = PathOptions {
+            radius: crate::TANK_SIZE.length() / 2.0,
+            ..Default::default()
+        };
+        assert!(tiles.find_path(Vec2::ZERO, from, to, &wide).is_none());
+    }
+}
@@ -0,0 +1,414 @@
+use std::time::Duration;
+
+use bevy::{prelude::*, render::camera::ScalingMode, sprite::Mesh2dHandle};
+use bevy_rapier2d::prelude::*;
+
+#[derive(Resource)]
+pub struct Materials {
+    pub bullet: Handle<Image>,
+    pub tank_base: Handle<Image>,
+    pub tank_gun: Handle<Image>,
+    pub explosion: Handle<TextureAtlas>,
+    pub wall_material: Handle<ColorMaterial>,
+}
+
+#[derive(Component)]
+struct AnimatedSpriteSheet {
+    first: usize,
+    last: usize,
+    timer: Timer,
+    despawn_on_end: bool,
+}
+
+/// Sprites, meshes and the camera for the game entities spawned by `TanksPlugin`.
+/// Not needed when running headless
+pub struct TanksRenderPlugin;
+
+impl Plugin for TanksRenderPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(PreStartup, load_materials);
+        app.add_systems(
+            Startup,
+            (spawn_camera, spawn_player_status, spawn_match_status),
+        );
+        app.add_systems(
+            Update,
+            (
+                attach_tank_sprites,
+                attach_gun_sprites,
+                attach_bullet_sprites,
+                attach_map_meshes,
+                show_spawn_protection,
+                update_player_status,
+                update_match_status,
+                attach_flag_sprites,
+                spawn_explosions,
+                animate_sprite,
+            ),
+        );
+        app.add_systems(PostUpdate, sync_player_camera);
+    }
+}
+
+fn load_materials(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut texture_atlases: ResMut<Assets<TextureAtlas>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    let explosion_handle = asset_server.load("explosion_sheet.png");
+    let explosion_atlas =
+        TextureAtlas::from_grid(explosion_handle, Vec2::new(256., 256.), 8, 6, None, None);
+    let explosion = texture_atlases.add(explosion_atlas);
+
+    // Walls are colored per vertex, see `Tile::color`
+    let wall_material = materials.add(ColorMaterial::from(Color::WHITE));
+
+    commands.insert_resource(Materials {
+        bullet: asset_server.load("bullet.png"),
+        tank_base: asset_server.load("tank_base.png"),
+        tank_gun: asset_server.load("tank_gun.png"),
+        explosion,
+        wall_material,
+    });
+}
+
+fn spawn_camera(mut commands: Commands) {
+    let projection = OrthographicProjection {
+        scaling_mode: ScalingMode::FixedVertical(20.0),
+        near: -1000.0,
+        far: 1000.0,
+        ..Default::default()
+    };
+
+    commands.spawn((
+        Camera2dBundle {
+            projection,
+            ..Default::default()
+        },
+        crate::StreamingAnchor,
+    ));
+}
+
+/// Returns the full size of a cuboid collider, used to size sprites to match their hitbox
+fn collider_size(collider: &Collider) -> Option<Vec2> {
+    collider
+        .as_cuboid()
+        .map(|cuboid| cuboid.half_extents() * 2.0)
+}
+
+/// Tanks on a team are tinted with its color
+fn attach_tank_sprites(
+    mut commands: Commands,
+    materials: Res<Materials>,
+    q_tank: Query<(Entity, &Collider, Option<&crate::Team>), Added<crate::TankBody>>,
+) {
+    for (entity, collider, team) in &q_tank {
+        commands.entity(entity).insert((
+            Sprite {
+                color: team.map_or(Color::WHITE, |team| team.color()),
+                custom_size: collider_size(collider),
+                ..Default::default()
+            },
+            materials.tank_base.clone(),
+        ));
+    }
+}
+
+fn attach_gun_sprites(
+    mut commands: Commands,
+    materials: Res<Materials>,
+    q_gun: Query<Entity, Added<crate::TankGun>>,
+) {
+    for entity in &q_gun {
+        commands.entity(entity).insert((
+            Sprite {
+                custom_size: Some(Vec2::new(619.0 / 300.0, 188.0 / 300.0)),
+                ..Default::default()
+            },
+            materials.tank_gun.clone(),
+        ));
+    }
+}
+
+fn attach_bullet_sprites(
+    mut commands: Commands,
+    materials: Res<Materials>,
+    q_bullet: Query<(Entity, &Collider), Added<crate::Bullet>>,
+) {
+    for (entity, collider) in &q_bullet {
+        commands.entity(entity).insert((
+            Sprite {
+                custom_size: collider_size(collider),
+                ..Default::default()
+            },
+            materials.bullet.clone(),
+        ));
+    }
+}
+
+/// Also rebuilds the mesh when a chunk's tiles change, e.g. on hot reload
+fn attach_map_meshes(
+    mut commands: Commands,
+    materials: Res<Materials>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    q_chunk: Query<(Entity, &crate::MapChunk), Changed<crate::MapChunk>>,
+) {
+    for (entity, chunk) in &q_chunk {
+        commands.entity(entity).insert((
+            Mesh2dHandle(meshes.add(chunk.tiles.build_mesh())),
+            materials.wall_material.clone(),
+        ));
+    }
+}
+
+/// Protected tanks are see-through
+fn show_spawn_protection(
+    mut q_tank: Query<(&mut Sprite, Option<&crate::SpawnProtection>), With<crate::TankBody>>,
+) {
+    for (mut sprite, protection) in &mut q_tank {
+        let alpha = if protection.is_some() { 0.5 } else { 1.0 };
+        if sprite.color.a() != alpha {
+            sprite.color.set_a(alpha);
+        }
+    }
+}
+
+fn attach_flag_sprites(
+    mut commands: Commands,
+    q_flag: Query<(Entity, &crate::Flag), Added<crate::Flag>>,
+) {
+    for (entity, flag) in &q_flag {
+        commands.entity(entity).insert((
+            Sprite {
+                color: flag.team.color(),
+                custom_size: Some(Vec2::splat(0.5)),
+                ..Default::default()
+            },
+            Handle::<Image>::default(),
+        ));
+    }
+}
+
+fn spawn_explosions(
+    mut commands: Commands,
+    materials: Res<Materials>,
+    mut explosions: EventReader<crate::ExplosionEvent>,
+) {
+    for explosion in explosions.iter() {
+        spawn_explosion(
+            &mut commands,
+            &materials,
+            explosion.pos,
+            explosion.size,
+            explosion.length,
+        );
+    }
+}
+
+fn animate_sprite(
+    time: Res<Time>,
+    mut commands: Commands,
+    mut query: Query<(Entity, &mut AnimatedSpriteSheet, &mut TextureAtlasSprite)>,
+) {
+    for (entity, mut animation, mut sprite) in &mut query {
+        if animation.timer.tick(time.delta()).just_finished() {
+            sprite.index = if sprite.index == animation.last - 1 {
+                if animation.despawn_on_end {
+                    commands.entity(entity).despawn_recursive();
+                    animation.first
+                } else {
+                    animation.first
+                }
+            } else {
+                sprite.index + 1
+            };
+        }
+    }
+}
+
+fn spawn_explosion(
+    commands: &mut Commands,
+    materials: &Res<Materials>,
+    pos: Vec2,
+    size: f32,
+    length: Duration,
+) {
+    let frames = 6 * 8;
+    commands.spawn((
+        SpriteSheetBundle {
+            texture_atlas: materials.explosion.clone(),
+            sprite: TextureAtlasSprite {
+                index: 0,
+                custom_size: Some(Vec2::splat(size)),
+                ..Default::default()
+            },
+            transform: Transform::from_xyz(pos.x, pos.y, 0.0),
+            ..default()
+        },
+        AnimatedSpriteSheet {
+            first: 0,
+            last: frames,
+            timer: Timer::new(length / frames as u32, TimerMode::Repeating),
+            despawn_on_end: true,
+        },
+    ));
+}
+
+pub fn sync_player_camera(
+    mut q_camera: Query<(
+        &mut Transform,
+        With<Camera2d>,
+        Without<crate::PlayerControlled>,
+    )>,
+    q_player: Query<(
+        &Transform,
+        With<crate::TankBody>,
+        With<crate::PlayerControlled>,
+    )>,
+    q_tank: Query<&Transform, (With<crate::TankBody>, Without<Camera2d>)>,
+    respawns: Res<crate::RespawnQueue>,
+) {
+    let Ok((mut camera, (), ())) = q_camera.get_single_mut() else {
+        return;
+    };
+
+    if let Ok((player, (), ())) = q_player.get_single() {
+        camera.translation = player.translation;
+        return;
+    }
+
+    // Watch whoever destroyed the player until it respawns, otherwise stay where it died
+    let killer = respawns
+        .player()
+        .and_then(|queued| q_tank.get(queued.killer?).ok());
+    if let Some(killer) = killer {
+        camera.translation = killer.translation;
+    }
+}
+
+/// Lives left, or how long until the player respawns
+#[derive(Component)]
+struct PlayerStatusText;
+
+fn spawn_player_status(mut commands: Commands) {
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font_size: 24.0,
+                color: Color::WHITE,
+                ..Default::default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Px(10.0),
+            left: Val::Px(10.0),
+            ..Default::default()
+        }),
+        PlayerStatusText,
+    ));
+}
+
+fn update_player_status(
+    settings: Res<crate::MatchSettings>,
+    rule: Res<crate::RespawnRule>,
+    respawns: Res<crate::RespawnQueue>,
+    q_player: Query<Option<&crate::Lives>, With<crate::PlayerControlled>>,
+    mut q_text: Query<&mut Text, With<PlayerStatusText>>,
+) {
+    let Ok(mut text) = q_text.get_single_mut() else {
+        return;
+    };
+
+    let status = if let Ok(lives) = q_player.get_single() {
+        match *rule {
+            crate::RespawnRule::Lives { lives: total, .. } => {
+                format!("Lives: {}", lives.map_or(total, |lives| lives.0 + 1))
+            }
+            _ => String::new(),
+        }
+    } else if let Some(queued) = respawns.player() {
+        format!("Respawning in {:.0}", queued.timer.remaining_secs().ceil())
+    } else if settings.human_player {
+        "Destroyed".to_owned()
+    } else {
+        String::new()
+    };
+
+    if text.sections[0].value != status {
+        text.sections[0].value = status;
+    }
+}
+
+/// Mode, time left, scores and the winner
+#[derive(Component)]
+struct MatchStatusText;
+
+fn spawn_match_status(mut commands: Commands) {
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font_size: 24.0,
+                color: Color::WHITE,
+                ..Default::default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Px(10.0),
+            right: Val::Px(10.0),
+            ..Default::default()
+        }),
+        MatchStatusText,
+    ));
+}
+
+fn update_match_status(
+    mode: Option<Res<crate::ActiveGameMode>>,
+    state: Option<Res<crate::MatchState>>,
+    mut q_text: Query<&mut Text, With<MatchStatusText>>,
+) {
+    let (Some(mode), Some(state)) = (mode, state) else {
+        return;
+    };
+    let Ok(mut text) = q_text.get_single_mut() else {
+        return;
+    };
+
+    let mut lines = vec![mode.0.name().to_owned()];
+    if mode.0.rounds() > 1 {
+        lines.push(format!("Round {}/{}", state.round, mode.0.rounds()));
+    }
+    if let Some(timer) = &state.round_timer {
+        let left = timer.remaining_secs().ceil() as u32;
+        lines.push(format!("{}:{:02}", left / 60, left % 60));
+    }
+
+    let mut scores: Vec<_> = state.scores.iter().collect();
+    scores.sort_by_key(|(side, score)| (-**score, side.to_string()));
+    lines.extend(
+        scores
+            .iter()
+            .map(|(side, score)| format!("{side}: {score}")),
+    );
+
+    match &state.phase {
+        crate::MatchPhase::Playing => {}
+        crate::MatchPhase::RoundOver { winner, .. } => lines.push(match winner {
+            Some(winner) => format!("{winner} wins the round"),
+            None => "Round drawn".to_owned(),
+        }),
+        crate::MatchPhase::Finished { winner } => lines.push(match winner {
+            Some(winner) => format!("{winner} wins the match"),
+            None => "Match drawn".to_owned(),
+        }),
+    }
+
+    let status = lines.join("\n");
+    if text.sections[0].value != status {
+        text.sections[0].value = status;
+    }
+}
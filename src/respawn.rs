@@ -0,0 +1,185 @@
+use bevy::prelude::*;
+
+/// Seconds a destroyed tank waits before it respawns, unless the rule says otherwise
+pub const DEFAULT_RESPAWN_DELAY: f32 = 3.0;
+
+/// Whether and when destroyed tanks come back, set from the `GameMode` or `MatchSettings::respawn`
+#[derive(Clone, Debug, Default, PartialEq, Resource)]
+pub enum RespawnRule {
+    /// Destroyed tanks stay destroyed
+    #[default]
+    Never,
+    /// Tanks respawn after `delay` seconds until they have been destroyed `lives` times
+    Lives { lives: u32, delay: f32 },
+    /// Tanks always respawn after `delay` seconds
+    Timed { delay: f32 },
+}
+
+/// Respawns left for a tank under `RespawnRule::Lives`. Tanks without it have not died yet, and
+/// have `lives - 1` respawns left
+#[derive(Clone, Component, Debug)]
+pub struct Lives(pub u32);
+
+/// A destroyed tank waiting to be spawned again with `spawn_tank`
+#[derive(Clone, Debug)]
+pub struct QueuedRespawn {
+    pub name: String,
+    pub archetype: String,
+    pub player_controlled: bool,
+    pub team: Option<crate::Team>,
+    /// Respawns left once this one is used, `None` without a limit
+    pub lives: Option<u32>,
+    /// The tank that destroyed it, for the camera to follow meanwhile
+    pub killer: Option<Entity>,
+    pub timer: Timer,
+}
+
+/// Tanks that will respawn, in the order they were destroyed
+#[derive(Clone, Debug, Default, Resource)]
+pub struct RespawnQueue(pub Vec<QueuedRespawn>);
+
+impl RespawnQueue {
+    /// The local player's tank, if it is waiting to respawn
+    pub fn player(&self) -> Option<&QueuedRespawn> {
+        self.0.iter().find(|queued| queued.player_controlled)
+    }
+}
+
+/// What a destroyed tank comes back as
+type RespawnedTank<'a> = (
+    &'a crate::TankArchetypeId,
+    Option<&'a crate::PlayerControlled>,
+    Option<&'a crate::Team>,
+    Option<&'a Lives>,
+);
+
+/// Queues destroyed tanks that have lives left. Runs before `destroy_tanks` despawns them
+fn queue_respawns(
+    rule: Res<RespawnRule>,
+    mut queue: ResMut<RespawnQueue>,
+    mut destroyed: EventReader<crate::TankDestroyedEvent>,
+    q_tank: Query<RespawnedTank>,
+) {
+    for event in destroyed.iter() {
+        let Ok((archetype, player, team, lives)) = q_tank.get(event.tank) else {
+            continue;
+        };
+
+        let (lives, delay) = match *rule {
+            RespawnRule::Never => continue,
+            RespawnRule::Lives {
+                lives: total,
+                delay,
+            } => {
+                let left = lives.map_or(total.saturating_sub(1), |lives| lives.0);
+                if left == 0 {
+                    continue;
+                }
+                (Some(left - 1), delay)
+            }
+            RespawnRule::Timed { delay } => (None, delay),
+        };
+
+        queue.0.push(QueuedRespawn {
+            name: event.name.clone(),
+            archetype: archetype.0.clone(),
+            player_controlled: player.is_some(),
+            team: team.copied(),
+            lives,
+            killer: event.killer,
+            timer: Timer::from_seconds(delay, TimerMode::Once),
+        });
+    }
+}
+
+fn respawn_tanks(mut commands: Commands, time: Res<FixedTime>, mut queue: ResMut<RespawnQueue>) {
+    for queued in &mut queue.0 {
+        queued.timer.tick(time.period);
+    }
+
+    let (ready, waiting): (Vec<_>, Vec<_>) = std::mem::take(&mut queue.0)
+        .into_iter()
+        .partition(|queued| queued.timer.finished());
+    queue.0 = waiting;
+
+    for queued in ready {
+        let tank = crate::spawn_tank(
+            &mut commands,
+            queued.name,
+            &queued.archetype,
+            queued.player_controlled,
+            queued.team,
+        );
+        if let Some(lives) = queued.lives {
+            commands.entity(tank).insert(Lives(lives));
+        }
+    }
+}
+
+pub fn init_respawn_systems(app: &mut App) {
+    app.init_resource::<RespawnQueue>();
+    app.init_resource::<RespawnRule>();
+    app.add_systems(
+        FixedUpdate,
+        (
+            queue_respawns
+                .after(crate::display_events)
+                .before(crate::destroy_tanks),
+            respawn_tanks.in_set(crate::TankSystemSet::ApplyInputs),
+        ),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs a deathmatch, destroying the tank named "Troy" whenever it is on the map. Returns
+    /// how many times it was placed
+    fn count_lives(rule: RespawnRule) -> usize {
+        let config = crate::HeadlessConfig {
+            mode: "deathmatch".to_owned(),
+            respawn: Some(rule),
+            ..Default::default()
+        };
+        let mut app = crate::build_headless_app(&config);
+
+        let mut placed = vec![];
+        for _ in 0..600 {
+            app.update();
+            let tank = app
+                .world
+                .query_filtered::<(Entity, &crate::TankBody, &Transform), Without<crate::PendingSpawn>>()
+                .iter(&app.world)
+                .find(|(_, tank, _)| tank.name == "Troy")
+                .map(|(entity, _, transform)| (entity, transform.translation.truncate()));
+            let Some((entity, pos)) = tank else {
+                continue;
+            };
+            if placed.last() == Some(&entity) {
+                continue;
+            }
+            placed.push(entity);
+            app.world.send_event(crate::TankDestroyedEvent {
+                tank: entity,
+                name: "Troy".to_owned(),
+                killer: None,
+                pos,
+            });
+        }
+        placed.len()
+    }
+
+    #[test]
+    fn lives_run_out() {
+        for lives in 1..=3 {
+            let rule = RespawnRule::Lives { lives, delay: 0.5 };
+            assert_eq!(count_lives(rule), lives as usize, "{lives} lives");
+        }
+    }
+
+    #[test]
+    fn timed_respawns_never_run_out() {
+        assert!(count_lives(RespawnRule::Timed { delay: 0.5 }) > 3);
+    }
+}
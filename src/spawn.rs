@@ -0,0 +1,266 @@
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+use rand::seq::SliceRandom;
+use rand_chacha::ChaChaRng;
+
+use crate::MapTiles;
+
+/// Tanks spawn at least this far from every other tank when there is room
+const SPAWN_MIN_SEPARATION: f32 = 5.0;
+/// Tanks closer than this overlap
+const SPAWN_MIN_CLEARANCE: f32 = 1.0;
+/// Team tanks spawn at most this far from their own flag in modes with flags
+const BASE_SPAWN_RADIUS: f32 = 4.0;
+/// Nobody spawns this close to a flag, so they can't pick it up straight away
+const BASE_CLEARANCE: f32 = 1.5;
+/// Spacing of the points checked along a line of sight
+const SIGHT_STEP: f32 = 0.25;
+/// Seconds a freshly spawned tank can't be damaged for
+pub const SPAWN_PROTECTION_TIME: f32 = 3.0;
+
+/// A tank waiting for `place_pending_tanks` to find it a spawn point once the maps are loaded.
+/// Its body and collider are disabled until then
+#[derive(Clone, Component, Debug)]
+pub struct PendingSpawn;
+
+/// Bullets don't damage a tank until `timer` finishes
+#[derive(Clone, Component, Debug)]
+pub struct SpawnProtection {
+    pub timer: Timer,
+}
+
+impl Default for SpawnProtection {
+    fn default() -> Self {
+        Self {
+            timer: Timer::from_seconds(SPAWN_PROTECTION_TIME, TimerMode::Once),
+        }
+    }
+}
+
+/// The map the match is played on. Tanks only spawn here, any other map is scenery
+#[derive(Clone, Component, Debug)]
+pub struct ArenaMap;
+
+/// Picks where tanks spawn on the `ArenaMap`
+pub struct SpawnResolver<'a> {
+    /// World position of the map's origin
+    map_pos: Vec2,
+    tiles: &'a MapTiles,
+    grid: crate::NavGrid,
+    /// Where each team's flag stands, indexed by `Team`. Empty in modes without flags
+    bases: Vec<Vec2>,
+}
+
+impl<'a> SpawnResolver<'a> {
+    pub fn new(map_pos: Vec2, tiles: &'a MapTiles) -> Self {
+        Self {
+            map_pos,
+            tiles,
+            grid: crate::NavGrid::new(tiles, &crate::PathOptions::default()),
+            bases: vec![],
+        }
+    }
+
+    /// Keeps tanks off the flags, and each team near its own flag, see `team_bases`
+    pub fn with_team_bases(mut self) -> Self {
+        self.bases = self.team_bases().map_or(vec![], Vec::from);
+        self
+    }
+
+    /// The two spawn points furthest apart, where the flags of the two teams stand
+    pub fn team_bases(&self) -> Option<[Vec2; 2]> {
+        let points = self.spawn_points();
+        points
+            .iter()
+            .enumerate()
+            .flat_map(|(i, a)| points[i + 1..].iter().map(move |b| [*a, *b]))
+            .max_by(|[a1, b1], [a2, b2]| a1.distance(*b1).total_cmp(&a2.distance(*b2)))
+    }
+
+    fn drivable(&self, p: IVec2) -> bool {
+        self.tiles.try_get(p).is_some_and(|t| !t.blocks_tanks())
+    }
+
+    /// Open tiles with room around them to spawn a tank on
+    fn open_tiles(&self) -> impl Iterator<Item = IVec2> + '_ {
+        self.tiles
+            .positions()
+            .filter(|p| self.drivable(*p) && self.tiles.neighbours(*p).all(|n| self.drivable(n)))
+    }
+
+    /// World positions of the map's spawn points a tank can stand on, or of every open tile when
+    /// it has none. Only the spawns in the largest region tanks can drive around are kept, so
+    /// every tank can reach every other
+    fn spawn_points(&self) -> Vec<Vec2> {
+        let mut points: Vec<IVec2> = self
+            .tiles
+            .spawn_points
+            .iter()
+            .copied()
+            .filter(|p| self.drivable(*p))
+            .collect();
+        if points.is_empty() {
+            points = self.open_tiles().collect();
+        }
+
+        largest_region(&self.grid, points)
+            .into_iter()
+            .map(|p| MapTiles::tile_to_world(self.map_pos, p))
+            .collect()
+    }
+
+    /// Where a tank on `team` may spawn. Team tanks spawn around their own flag when there are
+    /// flags, nobody spawns on a flag
+    pub fn candidates(&self, team: Option<crate::Team>) -> Vec<Vec2> {
+        let clear_of_bases = |p: &Vec2| self.bases.iter().all(|b| b.distance(*p) >= BASE_CLEARANCE);
+
+        if let Some(base) = team.and_then(|team| self.bases.get(team.0 as usize)) {
+            let field =
+                crate::FlowField::new(&self.grid, MapTiles::world_to_tile(self.map_pos, *base));
+            let near_base: Vec<Vec2> = self
+                .open_tiles()
+                .filter(|p| field.distance(*p).is_some())
+                .map(|p| MapTiles::tile_to_world(self.map_pos, p))
+                .filter(|p| clear_of_bases(p) && p.distance(*base) <= BASE_SPAWN_RADIUS)
+                .collect();
+            if !near_base.is_empty() {
+                return near_base;
+            }
+        }
+
+        self.spawn_points()
+            .into_iter()
+            .filter(clear_of_bases)
+            .collect()
+    }
+
+    /// Whether a bullet could fly from `from` to `to` without hitting a map tile
+    pub fn line_of_sight(&self, from: Vec2, to: Vec2) -> bool {
+        let steps = (from.distance(to) / SIGHT_STEP).ceil().max(1.0) as usize;
+        (0..=steps).all(|i| {
+            let p = from.lerp(to, i as f32 / steps as f32);
+            self.tiles
+                .tile_at_world(self.map_pos, p)
+                .is_none_or(|t| !t.blocks_bullets())
+        })
+    }
+
+    /// A spawn point for a tank on `team` at least `SPAWN_MIN_SEPARATION` from every tank at
+    /// `tanks` and out of their sight. When no spawn point is, the sight and then the separation
+    /// constraint are dropped. `None` when every spawn point is taken
+    pub fn resolve(
+        &self,
+        tanks: &[Vec2],
+        team: Option<crate::Team>,
+        rng: &mut ChaChaRng,
+    ) -> Option<Vec2> {
+        let free: Vec<Vec2> = self
+            .candidates(team)
+            .into_iter()
+            .filter(|c| tanks.iter().all(|t| t.distance(*c) >= SPAWN_MIN_CLEARANCE))
+            .collect();
+        let separated: Vec<Vec2> = free
+            .iter()
+            .copied()
+            .filter(|c| tanks.iter().all(|t| t.distance(*c) >= SPAWN_MIN_SEPARATION))
+            .collect();
+        let hidden: Vec<Vec2> = separated
+            .iter()
+            .copied()
+            .filter(|c| tanks.iter().all(|t| !self.line_of_sight(*c, *t)))
+            .collect();
+
+        [hidden, separated, free]
+            .into_iter()
+            .find(|candidates| !candidates.is_empty())?
+            .choose(rng)
+            .copied()
+    }
+}
+
+/// The `points` connected to the most others by paths a tank fits through
+fn largest_region(grid: &crate::NavGrid, mut points: Vec<IVec2>) -> Vec<IVec2> {
+    let mut largest = vec![];
+    while let Some(&start) = points.first() {
+        let field = crate::FlowField::new(grid, start);
+        let (region, rest): (Vec<IVec2>, Vec<IVec2>) = points
+            .into_iter()
+            .partition(|p| *p == start || field.distance(*p).is_some());
+        points = rest;
+        if region.len() > largest.len() {
+            largest = region;
+        }
+    }
+    largest
+}
+
+fn place_pending_tanks(
+    mut commands: Commands,
+    mut rng: ResMut<crate::GameRng>,
+    mode: Option<Res<crate::ActiveGameMode>>,
+    q_map: Query<(&GlobalTransform, &MapTiles), With<ArenaMap>>,
+    mut q_pending: Query<(Entity, &mut Transform, Option<&crate::Team>), With<PendingSpawn>>,
+    q_tank: Query<&Transform, (With<crate::TankBody>, Without<PendingSpawn>)>,
+) {
+    if q_pending.is_empty() {
+        return;
+    }
+    let Ok((map_transform, tiles)) = q_map.get_single() else {
+        return;
+    };
+
+    let mut resolver = SpawnResolver::new(map_transform.translation().truncate(), tiles);
+    if mode.is_some_and(|mode| mode.0.uses_flags()) {
+        resolver = resolver.with_team_bases();
+    }
+    let mut tanks: Vec<Vec2> = q_tank
+        .iter()
+        .map(|transform| transform.translation.truncate())
+        .collect();
+
+    // Same order every run, so matches replay the same
+    let mut pending: Vec<_> = q_pending.iter_mut().collect();
+    pending.sort_by_key(|(entity, _, _)| *entity);
+
+    for (entity, mut transform, team) in pending {
+        let pos = resolver
+            .resolve(&tanks, team.copied(), &mut rng.0)
+            .unwrap_or_else(|| {
+                warn!("No free spawn point for {entity:?}");
+                transform.translation.truncate()
+            });
+        transform.translation = pos.extend(transform.translation.z);
+        tanks.push(pos);
+
+        commands
+            .entity(entity)
+            .remove::<(PendingSpawn, RigidBodyDisabled, ColliderDisabled)>()
+            .insert(SpawnProtection::default());
+    }
+}
+
+fn tick_spawn_protection(
+    mut commands: Commands,
+    time: Res<FixedTime>,
+    mut q_protected: Query<(Entity, &mut SpawnProtection)>,
+) {
+    for (entity, mut protection) in &mut q_protected {
+        if protection.timer.tick(time.period).finished() {
+            commands.entity(entity).remove::<SpawnProtection>();
+        }
+    }
+}
+
+pub fn init_spawn_systems(app: &mut App) {
+    app.add_systems(
+        FixedUpdate,
+        (
+            // Needs the maps' `GlobalTransform`s, which aren't propagated yet on the first update
+            place_pending_tanks
+                .after(crate::FixedTransformPropagation)
+                .before(crate::TankSystemSet::Controllers)
+                .run_if(not(any_with_component::<crate::MapLoading>())),
+            tick_spawn_protection.in_set(crate::TankSystemSet::ApplyInputs),
+        ),
+    );
+}
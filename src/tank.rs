@@ -1,4 +1,4 @@
-use bevy::{prelude::*, window::PrimaryWindow};
+use bevy::prelude::*;
 use bevy_rapier2d::prelude::*;
 use std::f32::consts::{PI, SQRT_2};
 
@@ -10,13 +10,24 @@ pub struct TankGun {
 }
 
 impl TankGun {
-    pub fn new(max_ammo: usize) -> Self {
+    pub fn new(max_ammo: usize, reload_time: f32) -> Self {
         Self {
-            timer: Timer::from_seconds(1.0, TimerMode::Repeating),
+            timer: Timer::from_seconds(reload_time, TimerMode::Repeating),
             ammo: max_ammo,
             max_ammo,
         }
     }
+
+    pub fn set_stats(&mut self, max_ammo: usize, reload_time: f32) {
+        self.max_ammo = max_ammo;
+        self.ammo = self.ammo.min(max_ammo);
+        self.timer
+            .set_duration(std::time::Duration::from_secs_f32(reload_time));
+    }
+
+    pub fn ammo(&self) -> usize {
+        self.ammo
+    }
 }
 
 #[derive(Clone, Component, Debug)]
@@ -26,11 +37,28 @@ pub struct TankBody {
     pub name: String,
 }
 
-/// This entity should obtain its input from keyboard / mouse
+/// The map tile under the center of a tank, `Tile::Air` when off the map
+#[derive(Clone, Component, Debug)]
+pub struct TankGround(pub crate::Tile);
+
+/// Linear damping of a tank on tiles with full grip
+const TANK_LINEAR_DAMPING: f32 = 1.5;
+
+/// The tank controlled by the local player, followed by the camera
 #[derive(Clone, Component, Debug)]
 pub struct PlayerControlled;
 
-/// Input actions to tank, produced by user input or the AI
+/// Ordering of the systems that turn controller decisions into tank movement
+#[derive(SystemSet, Clone, Debug, Hash, PartialEq, Eq)]
+pub enum TankSystemSet {
+    /// Each tank's `Controller` decides on its `TankInputs`
+    Controllers,
+    /// `TankInputs` are applied to the tank body and gun
+    ApplyInputs,
+}
+
+/// Input actions to tank, produced by a `TankController`
+#[derive(Clone, Debug, Default, PartialEq)]
 pub struct TankBodyInput {
     /// (0..1) strength of forward action
     forward: f32,
@@ -40,6 +68,7 @@ pub struct TankBodyInput {
     rotate: f32,
 }
 
+#[derive(Clone, Debug, Default)]
 pub struct TankGunInput {
     /// Desired gun angle (radians)
     gun_angle: f32,
@@ -66,65 +95,73 @@ impl TankGunInput {
     }
 }
 
+/// Width and height of a tank's collider
+pub const TANK_SIZE: Vec2 = Vec2::new(0.9, 0.9);
+
 pub fn get_rotz(transform: &Transform) -> f32 {
     transform.rotation.to_euler(EulerRot::XYZ).2
 }
 
+/// Spawns a tank whose stats come from `assets/tanks/<archetype>.tank.ron`. It is placed on a
+/// spawn point once the maps are loaded, see `PendingSpawn`
 pub fn spawn_tank(
     commands: &mut Commands,
-    materials: &Res<crate::Materials>,
-    position: Vec2,
     name: String,
+    archetype: &str,
     player_controlled: bool,
+    team: Option<crate::Team>,
 ) -> Entity {
+    // Placeholder stats until `resolve_tank_stats` finds the archetype
+    let stats = crate::TankStats::default();
+
     let tank = {
-        let size = Vec2::new(0.9, 0.9);
-        let mut tank = commands.spawn(SpriteBundle {
-            sprite: Sprite {
-                custom_size: Some(size),
-                ..Default::default()
-            },
-            texture: materials.tank_base.clone(),
-            transform: Transform::from_xyz(position.x, position.y, 0.0),
-            ..Default::default()
-        });
+        let size = TANK_SIZE;
+        let mut tank = commands.spawn(SpatialBundle::default());
 
         tank.insert(TankBody { speed: 0.0, name })
+            .insert(crate::Health::new(stats.health))
+            .insert(stats.armor.clone())
+            .insert(crate::TankArchetypeId(archetype.to_owned()))
+            .insert(crate::TankStatsPending)
+            .insert(stats.clone())
+            .insert(crate::PendingSpawn)
             .insert(RigidBody::Dynamic)
+            .insert(RigidBodyDisabled)
             .insert(Velocity {
                 linvel: Vec2::new(0.0, 0.0),
                 angvel: 0.0,
             })
             .insert(Collider::cuboid(size.x / 2.0, size.y / 2.0))
+            .insert(ColliderDisabled)
+            .insert(crate::tank_groups(team))
             .insert(ColliderMassProperties::Density(20.0))
             // XY plane is flat base, no gravity
             .insert(GravityScale(0.0))
             .insert(Damping {
-                linear_damping: 1.5,
+                linear_damping: TANK_LINEAR_DAMPING,
                 angular_damping: 5.0,
             });
 
+        if let Some(team) = team {
+            tank.insert(team);
+        }
+        tank.insert(crate::TankInputs::default());
+        tank.insert(TankGround(crate::Tile::Air));
+        // Any tank can be hunted, AI tanks share a flow field to each one
+        tank.insert(crate::FlowFieldGoal);
         if player_controlled {
-            tank.insert(PlayerControlled);
+            tank.insert(PlayerControlled).insert(crate::Controller::new(
+                crate::KeyboardMouseController::default(),
+            ));
+        } else {
+            tank.insert(crate::Controller::new(crate::AiController::default()));
         }
         tank.id()
     };
 
     let gun = {
-        let mut gun = commands.spawn(SpriteBundle {
-            sprite: Sprite {
-                custom_size: Some(Vec2::new(619.0 / 300.0, 188.0 / 300.0)),
-                ..Default::default()
-            },
-            texture: materials.tank_gun.clone(),
-            transform: Transform::from_xyz(0.0, 0.0, 0.0),
-            ..Default::default()
-        });
-        gun.insert(TankGun::new(5));
-
-        if player_controlled {
-            gun.insert(PlayerControlled);
-        }
+        let mut gun = commands.spawn(SpatialBundle::default());
+        gun.insert(TankGun::new(stats.max_ammo, stats.reload_time));
         gun.id()
     };
 
@@ -133,32 +170,33 @@ pub fn spawn_tank(
     tank
 }
 
-fn reload_tank_guns(time: Res<Time>, mut q: Query<&mut TankGun>) {
+fn reload_tank_guns(time: Res<FixedTime>, mut q: Query<&mut TankGun>) {
     for mut gun in &mut q {
-        if gun.timer.tick(time.delta()).just_finished() {
+        if gun.timer.tick(time.period).just_finished() {
             gun.ammo = (gun.ammo + 1).clamp(0, gun.max_ammo);
         }
     }
 }
 
-const MAX_TANK_SPEED: f32 = 2.0;
-const TANK_ACCLERATION: f32 = 6.0;
-const BULLET_SHOOT_SPEED: f32 = 18.0;
-const TANK_BRAKING: f32 = 4.0;
-const TANK_ROTATE_RATE_DEGS: f32 = 140.0f32;
-const GUN_ROTATE_RATE_DEGS: f32 = 220.0f32;
+/// The tank a gun is mounted on, and what its bullets need to know about it
+pub struct Shooter<'a> {
+    pub entity: Entity,
+    pub stats: &'a crate::TankStats,
+    pub team: Option<crate::Team>,
+    pub collider: &'a Collider,
+    pub rules: &'a crate::TeamRules,
+}
 
-fn update_tank_gun_input(
+pub fn update_tank_gun_input(
     commands: &mut Commands,
-    materials: &Res<crate::Materials>,
-    time: &Res<Time>,
+    dt: f32,
     input: &TankGunInput,
     local: &mut Transform,
     global: &GlobalTransform,
     gun: &mut TankGun,
-    tank_entity: Entity,
-    q_collider: &Query<&Collider>,
+    shooter: &Shooter,
 ) {
+    let stats = shooter.stats;
     let transform = global.compute_transform();
 
     let desired = Quat::from_rotation_z(input.gun_angle);
@@ -171,7 +209,7 @@ fn update_tank_gun_input(
     };
 
     // How many radians should we step closer to `desired` this update
-    let scalar_step = GUN_ROTATE_RATE_DEGS.to_radians() * time.delta_seconds();
+    let scalar_step = stats.gun_rotate_rate_degs.to_radians() * dt;
 
     // interpolation factor between our current rotation and desired
     let f = (scalar_step / angular_error).clamp(0.0, 1.0);
@@ -186,181 +224,199 @@ fn update_tank_gun_input(
         local.rotate(step);
     }
 
-    if input.shoot {
-        if gun.ammo > 0 {
-            gun.ammo -= 1;
-            let collider = q_collider
-                .get(tank_entity)
-                .expect("Parent of gun entity must have collider");
-            let tank_pos = transform.translation.truncate();
-
-            let tank_extents = collider
-                .as_cuboid()
-                .expect("Only cubiod colliders are allowed for tanks")
-                .raw
-                .half_extents;
-
-            let bullet_size = Vec2::new(0.2, 0.2);
-            // "radius" is the radius of the circle that inscribes the bounding box
-            // (prevents the bullet from colliding with the shooting tank immediately)
-            let radius = (tank_extents.x.max(tank_extents.y) + bullet_size.x) * SQRT_2;
-
-            let bullet_velocity_unit = Vec2::from_angle(gun_angle);
-            // Spawn bullet outside of the tank's hitbox
-            let bullet_pos = tank_pos + bullet_velocity_unit * radius;
-            let bullet_velocity = BULLET_SHOOT_SPEED * bullet_velocity_unit;
-
-            crate::spawn_bullet(
-                commands,
-                materials,
-                tank_entity,
-                bullet_size,
-                bullet_pos,
-                bullet_velocity,
-            );
-        }
+    if input.shoot && gun.ammo > 0 {
+        gun.ammo -= 1;
+        let tank_pos = transform.translation.truncate();
+
+        let tank_extents = shooter
+            .collider
+            .as_cuboid()
+            .expect("Only cubiod colliders are allowed for tanks")
+            .raw
+            .half_extents;
+
+        let bullet_size = Vec2::new(0.2, 0.2);
+        // "radius" is the radius of the circle that inscribes the bounding box
+        // (prevents the bullet from colliding with the shooting tank immediately)
+        let radius = (tank_extents.x.max(tank_extents.y) + bullet_size.x) * SQRT_2;
+
+        let bullet_velocity_unit = Vec2::from_angle(gun_angle);
+        // Spawn bullet outside of the tank's hitbox
+        let bullet_pos = tank_pos + bullet_velocity_unit * radius;
+        let bullet_velocity = stats.bullet_speed * bullet_velocity_unit;
+
+        crate::spawn_bullet(
+            commands,
+            crate::Bullet::new(
+                shooter.entity,
+                shooter.team,
+                stats.bullet_damage,
+                stats.bullet_bounces,
+                stats.bullet_lifetime,
+                stats.bullet_max_range,
+            ),
+            shooter.rules,
+            bullet_size,
+            bullet_pos,
+            bullet_velocity,
+        );
     }
 }
 
-fn update_tank_body_input(
-    time: &Res<Time>,
+pub fn update_tank_body_input(
+    dt: f32,
     input: &TankBodyInput,
+    stats: &crate::TankStats,
+    ground: crate::Tile,
     transform: &mut Transform,
     vel: &mut Velocity,
     body: &mut TankBody,
 ) {
     let mut accerlating = false;
+    let max_speed = stats.max_speed * ground.speed_scale();
 
     if input.rotate != 0.0 {
-        transform
-            .rotate_z(input.rotate * TANK_ROTATE_RATE_DEGS.to_radians() * time.delta_seconds());
+        transform.rotate_z(input.rotate * stats.rotate_rate_degs.to_radians() * dt);
     }
 
     if input.forward != 0.0 {
-        body.speed += input.forward * TANK_ACCLERATION * time.delta_seconds();
+        body.speed += input.forward * stats.acceleration * dt;
         accerlating = true;
     }
 
     if input.backward != 0.0 {
-        body.speed -= input.backward * TANK_ACCLERATION * time.delta_seconds();
+        body.speed -= input.backward * stats.acceleration * dt;
         accerlating = true;
     }
-    body.speed = body.speed.clamp(-MAX_TANK_SPEED, MAX_TANK_SPEED);
+    body.speed = body.speed.clamp(-max_speed, max_speed);
 
     // brake if no forward or backward inputs are given
     if !accerlating {
-        let decrease = TANK_BRAKING * time.delta_seconds();
+        let decrease = stats.braking * ground.grip() * dt;
         let decrease = decrease.clamp(0.0, body.speed.abs());
         body.speed -= body.speed.signum() * decrease;
     }
 
-    let rotation = get_rotz(&transform);
+    let rotation = get_rotz(transform);
 
-    vel.linvel = Vec2::from_angle(rotation) * body.speed;
+    // With little grip the tank keeps sliding the way it was going
+    let wanted = Vec2::from_angle(rotation) * body.speed;
+    vel.linvel = vel.linvel.lerp(wanted, ground.grip());
 }
 
-pub fn sync_player_camera(
-    mut q_camera: Query<(
-        &mut Transform,
-        With<Camera2d>,
-        Without<crate::PlayerControlled>,
-    )>,
-    q_player: Query<(
-        &mut Transform,
-        With<crate::TankBody>,
-        With<crate::PlayerControlled>,
-    )>,
+/// Finds the tile under each tank, and lowers its damping on slippery ground
+fn update_tank_ground(
+    q_map: Query<(&GlobalTransform, &crate::MapTiles)>,
+    mut q_tank: Query<(&Transform, &mut TankGround, &mut Damping)>,
 ) {
-    let Ok((mut camera, (), ())) = q_camera.get_single_mut() else {
-        return;
-    };
-
-    let Ok((player, (), ())) = q_player.get_single() else {
-        return;
-    };
+    for (transform, mut ground, mut damping) in &mut q_tank {
+        let pos = transform.translation.truncate();
+        let tile = q_map
+            .iter()
+            .find_map(|(map_transform, tiles)| {
+                tiles.tile_at_world(map_transform.translation().truncate(), pos)
+            })
+            .unwrap_or(crate::Tile::Air);
 
-    camera.translation = player.translation;
+        if ground.0 != tile {
+            ground.0 = tile;
+            damping.linear_damping = TANK_LINEAR_DAMPING * tile.grip();
+        }
+    }
 }
 
+type InputTankBody<'a> = (
+    &'a mut Transform,
+    &'a mut Velocity,
+    &'a mut TankBody,
+    &'a crate::TankInputs,
+    &'a crate::TankStats,
+    &'a TankGround,
+);
+
 fn update_tank_body_input_system(
-    time: Res<Time>,
-    keys: Res<Input<KeyCode>>,
-    mut q_tank: Query<(
-        &mut Transform,
-        &mut Velocity,
-        &mut TankBody,
-        With<PlayerControlled>,
-    )>,
+    time: Res<FixedTime>,
+    mut q_tank: Query<InputTankBody, Without<crate::PendingSpawn>>,
 ) {
-    for (mut transform, mut vel, mut body, _) in &mut q_tank {
-        let forward = keys.pressed(KeyCode::Up).then(|| 1.0).unwrap_or(0.0);
-        let backward = keys.pressed(KeyCode::Down).then(|| 1.0).unwrap_or(0.0);
-
-        let left_rotate = keys.pressed(KeyCode::Left).then(|| 1.0).unwrap_or(0.0);
-        let right_rotate = keys.pressed(KeyCode::Right).then(|| -1.0).unwrap_or(0.0);
-
-        let rotate = left_rotate + right_rotate;
-
-        let input = TankBodyInput::new(forward, backward, rotate);
-        update_tank_body_input(&time, &input, &mut transform, &mut vel, &mut body);
+    for (mut transform, mut vel, mut body, inputs, stats, ground) in &mut q_tank {
+        update_tank_body_input(
+            time.period.as_secs_f32(),
+            &inputs.body,
+            stats,
+            ground.0,
+            &mut transform,
+            &mut vel,
+            &mut body,
+        );
     }
 }
 
 fn update_tank_gun_input_system(
     mut commands: Commands,
-    materials: Res<crate::Materials>,
-    time: Res<Time>,
-    window: Query<&Window, With<PrimaryWindow>>,
-    buttons: Res<Input<MouseButton>>,
-    mut q_gun: Query<(
-        &mut Transform,
-        &GlobalTransform,
-        &mut TankGun,
-        &Parent,
-        With<PlayerControlled>,
-    )>,
-    q_camera: Query<(&Camera, &GlobalTransform)>,
-    q_collider: Query<&Collider>,
+    time: Res<FixedTime>,
+    rules: Res<crate::TeamRules>,
+    mut q_gun: Query<(&mut Transform, &GlobalTransform, &mut TankGun, &Parent)>,
+    q_tank: Query<
+        (
+            &crate::TankInputs,
+            &crate::TankStats,
+            Option<&crate::Team>,
+            &Collider,
+        ),
+        Without<crate::PendingSpawn>,
+    >,
 ) {
-    let Ok((camera, camera_transform)) = q_camera.get_single() else {
-        return;
-    };
-
-    let Ok(cursor_pos) = window.get_single().map(|w| w.cursor_position()) else {
-        return;
-    };
-    for (mut local, global, mut gun, parent, _) in &mut q_gun {
-        let tank_pos = global.translation();
-        let gun_angle = cursor_pos
-            .and_then(|cursor| camera.viewport_to_world(camera_transform, cursor))
-            .map(|ray| {
-                let delta = ray.origin.truncate() - tank_pos.truncate();
-                let r = f32::atan2(delta.y, delta.x);
-                r
-            })
-            // target current rotation if mouse outside screen
-            .unwrap_or_else(|| get_rotz(&global.compute_transform()));
-
-        let shoot = buttons.just_pressed(MouseButton::Left);
-        let input = TankGunInput::new(gun_angle, shoot);
+    for (mut local, global, mut gun, parent) in &mut q_gun {
+        let Ok((inputs, stats, team, collider)) = q_tank.get(parent.get()) else {
+            continue;
+        };
+        let shooter = Shooter {
+            entity: parent.get(),
+            stats,
+            team: team.copied(),
+            collider,
+            rules: &rules,
+        };
 
         update_tank_gun_input(
             &mut commands,
-            &materials,
-            &time,
-            &input,
+            time.period.as_secs_f32(),
+            &inputs.gun,
             &mut local,
             global,
             &mut gun,
-            parent.get(),
-            &q_collider,
+            &shooter,
         )
     }
 }
 
 pub fn init_tank_systems(app: &mut App) {
-    app.add_systems(Update, update_tank_body_input_system);
-    app.add_systems(Update, update_tank_gun_input_system);
-    app.add_systems(Update, reload_tank_guns);
-    app.add_systems(PostUpdate, sync_player_camera);
+    app.configure_sets(
+        FixedUpdate,
+        (TankSystemSet::Controllers, TankSystemSet::ApplyInputs).chain(),
+    );
+    // Keep everything still until all tanks know their stats and the maps are built, so matches
+    // replay the same no matter how long loading took. Also between rounds and once the match is
+    // over. Tanks waiting for a spawn point are left out by the systems' queries instead, so a
+    // respawning tank doesn't hold up the others
+    for set in [TankSystemSet::Controllers, TankSystemSet::ApplyInputs] {
+        app.configure_set(
+            FixedUpdate,
+            set.run_if(
+                not(any_with_component::<crate::TankStatsPending>())
+                    .and_then(not(any_with_component::<crate::MapLoading>()))
+                    .and_then(crate::match_in_progress),
+            ),
+        );
+    }
+    app.add_systems(
+        FixedUpdate,
+        (
+            reload_tank_guns.before(update_tank_gun_input_system),
+            update_tank_ground.before(update_tank_body_input_system),
+            update_tank_body_input_system,
+            update_tank_gun_input_system,
+        )
+            .in_set(TankSystemSet::ApplyInputs),
+    );
 }
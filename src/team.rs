@@ -0,0 +1,209 @@
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+
+/// Collision groups of bullets that pass through tanks of their own team, indexed by `Team`.
+/// Teams past the last one always hit their allies
+const TEAM_BULLET_GROUPS: [Group; 4] = [
+    Group::GROUP_5,
+    Group::GROUP_6,
+    Group::GROUP_7,
+    Group::GROUP_8,
+];
+
+/// Which side a tank plays for, tanks on the same team are allies. Tanks without one are enemies
+/// of every other tank
+#[derive(Copy, Clone, Component, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Team(pub u8);
+
+impl Team {
+    pub fn name(self) -> &'static str {
+        ["Red", "Blue", "Green", "Yellow"]
+            .get(self.0 as usize)
+            .copied()
+            .unwrap_or("Other")
+    }
+
+    pub fn color(self) -> Color {
+        [Color::RED, Color::BLUE, Color::GREEN, Color::YELLOW]
+            .get(self.0 as usize)
+            .copied()
+            .unwrap_or(Color::GRAY)
+    }
+
+    fn bullet_group(self) -> Option<Group> {
+        TEAM_BULLET_GROUPS.get(self.0 as usize).copied()
+    }
+}
+
+/// Whether two tanks are on the same team
+pub fn allies(a: Option<Team>, b: Option<Team>) -> bool {
+    matches!((a, b), (Some(a), Some(b)) if a == b)
+}
+
+/// What happens when a bullet hits a tank on the shooter's team
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum FriendlyFire {
+    /// The bullet is spent but does no damage
+    #[default]
+    Off,
+    /// Allies are damaged like enemies
+    Damage,
+    /// The shooter takes the damage instead
+    Reflect,
+}
+
+impl FriendlyFire {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "off" => Some(Self::Off),
+            "damage" => Some(Self::Damage),
+            "reflect" => Some(Self::Reflect),
+            _ => None,
+        }
+    }
+}
+
+/// How teammates interact, set from `MatchSettings::team_rules`
+#[derive(Clone, Debug, Default, PartialEq, Resource)]
+pub struct TeamRules {
+    pub friendly_fire: FriendlyFire,
+    /// Bullets fly through tanks of the shooter's team instead of hitting them
+    pub bullets_pass_allies: bool,
+}
+
+impl TeamRules {
+    /// Collision groups for a bullet fired by a tank on `team`
+    pub fn bullet_groups(&self, team: Option<Team>) -> CollisionGroups {
+        let memberships = team
+            .filter(|_| self.bullets_pass_allies)
+            .and_then(Team::bullet_group)
+            .unwrap_or(crate::BULLET_GROUP);
+        CollisionGroups::new(
+            memberships,
+            crate::WALL_GROUP | crate::TANK_GROUP | all_bullet_groups(),
+        )
+    }
+
+    /// The tank that takes the damage when a bullet fired by `shooter` hits `hit`, `None` when
+    /// the hit is on an ally and friendly fire is off
+    pub fn damaged_tank(
+        &self,
+        shooter: (Entity, Option<Team>),
+        hit: (Entity, Option<Team>),
+    ) -> Option<Entity> {
+        if !allies(shooter.1, hit.1) {
+            return Some(hit.0);
+        }
+        match self.friendly_fire {
+            FriendlyFire::Off => None,
+            FriendlyFire::Damage => Some(hit.0),
+            FriendlyFire::Reflect => Some(shooter.0),
+        }
+    }
+}
+
+/// Collision groups for a tank on `team`, which ignores bullets that pass through its allies
+pub fn tank_groups(team: Option<Team>) -> CollisionGroups {
+    let ignored = team.and_then(Team::bullet_group).unwrap_or(Group::NONE);
+    CollisionGroups::new(crate::TANK_GROUP, Group::ALL - ignored)
+}
+
+/// Every group a bullet can be a member of, so bullets of any team can shoot each other down
+pub fn all_bullet_groups() -> Group {
+    TEAM_BULLET_GROUPS
+        .iter()
+        .fold(crate::BULLET_GROUP, |groups, group| groups | *group)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Whether Rapier lets colliders with these groups touch
+    fn interacts(a: CollisionGroups, b: CollisionGroups) -> bool {
+        a.memberships.intersects(b.filters) && b.memberships.intersects(a.filters)
+    }
+
+    const TEAMS: [Option<Team>; 4] = [None, Some(Team(0)), Some(Team(1)), Some(Team(4))];
+
+    #[test]
+    fn bullets_hit_every_tank_by_default() {
+        let rules = TeamRules::default();
+        for shooter in TEAMS {
+            for tank in TEAMS {
+                assert!(interacts(rules.bullet_groups(shooter), tank_groups(tank)));
+            }
+        }
+    }
+
+    #[test]
+    fn bullets_pass_allies() {
+        let rules = TeamRules {
+            bullets_pass_allies: true,
+            ..Default::default()
+        };
+        for shooter in TEAMS {
+            for tank in TEAMS {
+                // Teams without a bullet group of their own can't pass their allies
+                let passes =
+                    allies(shooter, tank) && shooter.and_then(Team::bullet_group).is_some();
+                assert_eq!(
+                    interacts(rules.bullet_groups(shooter), tank_groups(tank)),
+                    !passes,
+                    "{shooter:?} shooting {tank:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn bullets_hit_walls_and_each_other() {
+        let wall = CollisionGroups::new(crate::WALL_GROUP, Group::ALL);
+        for bullets_pass_allies in [false, true] {
+            let rules = TeamRules {
+                bullets_pass_allies,
+                ..Default::default()
+            };
+            for a in TEAMS {
+                assert!(interacts(rules.bullet_groups(a), wall));
+                for b in TEAMS {
+                    assert!(interacts(rules.bullet_groups(a), rules.bullet_groups(b)));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn tanks_collide_with_each_other() {
+        for a in TEAMS {
+            for b in TEAMS {
+                assert!(interacts(tank_groups(a), tank_groups(b)));
+            }
+        }
+    }
+
+    #[test]
+    fn friendly_fire_picks_the_damaged_tank() {
+        let shooter = Entity::from_raw(1);
+        let hit = Entity::from_raw(2);
+        let damaged = |friendly_fire, hit_team| {
+            let rules = TeamRules {
+                friendly_fire,
+                ..Default::default()
+            };
+            rules.damaged_tank((shooter, Some(Team(0))), (hit, hit_team))
+        };
+
+        for friendly_fire in [
+            FriendlyFire::Off,
+            FriendlyFire::Damage,
+            FriendlyFire::Reflect,
+        ] {
+            assert_eq!(damaged(friendly_fire, Some(Team(1))), Some(hit));
+            assert_eq!(damaged(friendly_fire, None), Some(hit));
+        }
+        assert_eq!(damaged(FriendlyFire::Off, Some(Team(0))), None);
+        assert_eq!(damaged(FriendlyFire::Damage, Some(Team(0))), Some(hit));
+        assert_eq!(damaged(FriendlyFire::Reflect, Some(Team(0))), Some(shooter));
+    }
+}